@@ -0,0 +1,88 @@
+// Heap buffer with a guaranteed alignment, for O_DIRECT I/O.
+//
+// Wraps the posix_memalign/free pair so the memory is released on every path,
+// including early returns and panics, and callers only ever see a &[u8].
+
+use std::{
+    fs::File,
+    io,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+    slice,
+};
+
+/// Used when neither statx nor sysfs know better (tmpfs, overlayfs, ...).
+pub const DEFAULT_ALIGNMENT: usize = 4096;
+
+pub struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    align: usize,
+}
+
+// The buffer owns its allocation exclusively, like a Box<[u8]>.
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+impl AlignedBuf {
+    /// Allocate `len` zeroed bytes aligned to `align`.
+    ///
+    /// `align` must be a power of two and a multiple of `size_of::<*const ()>()`,
+    /// as required by posix_memalign.
+    pub fn new(len: usize, align: usize) -> io::Result<Self> {
+        if !align.is_power_of_two() || !align.is_multiple_of(size_of::<*const ()>()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid alignment {}", align),
+            ));
+        }
+
+        let mut raw: *mut libc::c_void = ptr::null_mut();
+        // posix_memalign(0) may return NULL; always ask for at least one byte.
+        let rc = unsafe { libc::posix_memalign(&mut raw, align, len.max(1)) };
+        if rc != 0 {
+            return Err(io::Error::from_raw_os_error(rc));
+        }
+        let ptr = NonNull::new(raw as *mut u8).ok_or(io::ErrorKind::OutOfMemory)?;
+        unsafe { ptr::write_bytes(ptr.as_ptr(), 0, len) };
+
+        Ok(Self { ptr, len, align })
+    }
+
+    /// Allocate `len` bytes aligned to `align`, every byte set to `byte`.
+    pub fn filled(len: usize, align: usize, byte: u8) -> io::Result<Self> {
+        let mut buf = Self::new(len, align)?;
+        buf.fill(byte);
+        Ok(buf)
+    }
+
+    /// Allocate a buffer suitable for O_DIRECT on `file`, i.e. aligned to the
+    /// direct I/O alignment reported for it (see [`crate::probe`]).
+    pub fn for_file(file: &File, len: usize) -> io::Result<Self> {
+        Self::new(len, crate::probe::alignment(file))
+    }
+
+    pub fn alignment(&self) -> usize {
+        self.align
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { libc::free(self.ptr.as_ptr() as *mut _) };
+    }
+}
@@ -0,0 +1,39 @@
+use std::{
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    os::unix::fs::OpenOptionsExt,
+};
+
+use super::{Counted, Recorder, WriteConfig, durability::Syncer, preallocate};
+
+// BufWriter in front of the file: many small application writes, few syscalls.
+pub fn run(cfg: &WriteConfig, rec: &mut Recorder) -> std::io::Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .custom_flags(cfg.durability.open_flags())
+        .open(&cfg.path)?;
+    preallocate(cfg, &file, rec)?;
+    let mut writer = BufWriter::new(Counted::new(file));
+    let mut syncer = Syncer::new(cfg.durability);
+
+    let chunk = vec![b'A'; cfg.block_size];
+
+    for i in 0..cfg.blocks() {
+        let len = cfg.block_len(i);
+        rec.op(|| writer.write_all(&chunk[..len]))?;
+        rec.bytes += len as u64;
+
+        if syncer.wrote(len as u64) {
+            // whatever is still sitting in the BufWriter isn't in the kernel yet
+            writer.flush()?;
+            syncer.sync(writer.get_ref().get_ref(), rec)?;
+        }
+    }
+
+    writer.flush()?;
+    syncer.finish(writer.get_ref().get_ref(), rec)?;
+    rec.syscalls += writer.get_ref().calls;
+    Ok(())
+}
@@ -0,0 +1,600 @@
+// Several writers, one file. Each worker (a thread or a forked process, each
+// with its own open file) appends fixed-size records, and afterwards the file
+// is read back to check that every record arrived whole and exactly once.
+//
+//   append    O_APPEND, one write(2) per record: the kernel picks the offset
+//   pwrite    every worker owns a disjoint range and pwrite(2)s into it
+//   flock     flock(LOCK_EX), lseek(SEEK_END), write, unlock
+//   fcntl     the same under an F_SETLKW record lock on the whole file
+//   unlocked  lseek(SEEK_END) and write with no lock: the race the others
+//             avoid, to show that the check catches it
+//
+// POSIX record locks belong to the process, so threads of one process don't
+// exclude each other with them; fcntl only makes sense with processes.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    os::unix::fs::FileExt,
+    path::PathBuf,
+    str::FromStr,
+    sync::Barrier,
+    thread,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    sys::wait::{WaitStatus, waitpid},
+    unistd::{ForkResult, fork, pipe},
+};
+
+use super::lookup;
+use crate::{
+    histogram::{Histogram, fmt_nanos},
+    lock::{self, Mode, Range},
+    report::Record,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Append,
+    Pwrite,
+    Flock,
+    Fcntl,
+    Unlocked,
+}
+
+impl Method {
+    pub const ALL: [Method; 5] = [
+        Method::Append,
+        Method::Pwrite,
+        Method::Flock,
+        Method::Fcntl,
+        Method::Unlocked,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Append => "append",
+            Method::Pwrite => "pwrite",
+            Method::Flock => "flock",
+            Method::Fcntl => "fcntl",
+            Method::Unlocked => "unlocked",
+        }
+    }
+
+    /// Whether concurrent writers are expected to leave an intact file.
+    pub fn is_safe(self) -> bool {
+        self != Method::Unlocked
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lookup(&Method::ALL, s, |m| m.name(), "concurrent write method")
+    }
+}
+
+/// What the workers are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Spawn {
+    Threads,
+    Processes,
+}
+
+impl fmt::Display for Spawn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Spawn::Threads => "threads",
+            Spawn::Processes => "processes",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ConcurrentConfig {
+    pub path: PathBuf,
+    pub workers: usize,
+    pub spawn: Spawn,
+    /// Records written by each worker.
+    pub records: u64,
+    /// Bytes per record, header included.
+    pub record_size: usize,
+}
+
+impl Default for ConcurrentConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("concurrent.dat"),
+            workers: 4,
+            spawn: Spawn::Threads,
+            records: 10_000,
+            record_size: 256,
+        }
+    }
+}
+
+// worker, sequence number, checksum; the rest is filler
+const HEADER: usize = 24;
+
+/// Smallest record that still has room for a byte of filler.
+pub const MIN_RECORD_SIZE: usize = HEADER + 1;
+
+// FNV-1a over everything but the checksum field itself.
+fn checksum(record: &[u8]) -> u64 {
+    record[..16]
+        .iter()
+        .chain(&record[HEADER..])
+        .fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+            (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+fn fill_record(buf: &mut [u8], worker: u64, seq: u64) {
+    buf[..8].copy_from_slice(&worker.to_le_bytes());
+    buf[8..16].copy_from_slice(&seq.to_le_bytes());
+    buf[HEADER..].fill(b'a' + (worker % 26) as u8);
+    let sum = checksum(buf);
+    buf[16..HEADER].copy_from_slice(&sum.to_le_bytes());
+}
+
+// (worker, seq) if the record is intact.
+fn parse_record(buf: &[u8]) -> Option<(u64, u64)> {
+    let field = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+    (field(16) == checksum(buf)).then(|| (field(0), field(8)))
+}
+
+/// What one worker measured.
+#[derive(Clone, Debug)]
+pub struct WorkerStats {
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Whole record writes, lock and unlock included.
+    pub latency: Histogram,
+    /// Time spent waiting for the lock; empty for lock-free methods.
+    pub lock_wait: Histogram,
+}
+
+impl WorkerStats {
+    fn to_words(&self) -> Vec<u64> {
+        let mut words = vec![self.bytes, self.elapsed.as_nanos() as u64];
+        self.latency.to_words(&mut words);
+        self.lock_wait.to_words(&mut words);
+        words
+    }
+
+    fn from_words(words: &[u64]) -> Option<Self> {
+        let mut words = words.iter().copied();
+        Some(Self {
+            bytes: words.next()?,
+            elapsed: Duration::from_nanos(words.next()?),
+            latency: Histogram::from_words(&mut words)?,
+            lock_wait: Histogram::from_words(&mut words)?,
+        })
+    }
+}
+
+// The lock a method takes around each record, if any.
+fn lock_kind(method: Method) -> Option<lock::Kind> {
+    match method {
+        Method::Flock => Some(lock::Kind::Flock),
+        Method::Fcntl => Some(lock::Kind::Posix),
+        _ => None,
+    }
+}
+
+fn worker(cfg: &ConcurrentConfig, method: Method, id: u64) -> io::Result<WorkerStats> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .append(method == Method::Append)
+        .open(&cfg.path)?;
+    let mut buf = vec![0u8; cfg.record_size];
+    let mut stats = WorkerStats {
+        bytes: 0,
+        elapsed: Duration::ZERO,
+        latency: Histogram::new(),
+        lock_wait: Histogram::new(),
+    };
+
+    let start = Instant::now();
+    for seq in 0..cfg.records {
+        fill_record(&mut buf, id, seq);
+        let op_start = Instant::now();
+        match method {
+            Method::Append => {
+                // a second write for the rest could land after someone
+                // else's record, so a short write is a failure here
+                let n = file.write(&buf)?;
+                if n != buf.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        format!("short O_APPEND write: {} of {} bytes", n, buf.len()),
+                    ));
+                }
+            }
+            Method::Pwrite => {
+                let offset = (id * cfg.records + seq) * cfg.record_size as u64;
+                file.write_all_at(&buf, offset)?;
+            }
+            Method::Flock | Method::Fcntl | Method::Unlocked => {
+                let kind = lock_kind(method);
+                if let Some(kind) = kind {
+                    lock::lock(&file, kind, Mode::Exclusive, Range::WHOLE, true)?;
+                    stats.lock_wait.record_duration(op_start.elapsed());
+                }
+                file.seek(SeekFrom::End(0))?;
+                file.write_all(&buf)?;
+                if let Some(kind) = kind {
+                    lock::unlock(&file, kind, Range::WHOLE)?;
+                }
+            }
+        }
+        stats.latency.record_duration(op_start.elapsed());
+        stats.bytes += buf.len() as u64;
+    }
+    stats.elapsed = start.elapsed();
+    Ok(stats)
+}
+
+fn run_threads(cfg: &ConcurrentConfig, method: Method) -> io::Result<(Duration, Vec<WorkerStats>)> {
+    // everyone starts writing at once, not as soon as their thread is up
+    let gate = Barrier::new(cfg.workers + 1);
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..cfg.workers as u64)
+            .map(|id| {
+                let gate = &gate;
+                s.spawn(move || {
+                    gate.wait();
+                    let start = Instant::now();
+                    worker(cfg, method, id).map(|stats| (start, Instant::now(), stats))
+                })
+            })
+            .collect();
+        gate.wait();
+        let runs = handles
+            .into_iter()
+            .map(|h| {
+                h.join()
+                    .unwrap_or_else(|_| Err(io::Error::other("worker panicked")))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        // from the first worker to start to the last to finish; the main
+        // thread may only wake up after the workers are well under way
+        let first = runs.iter().map(|r| r.0).min();
+        let last = runs.iter().map(|r| r.1).max();
+        let elapsed = match (first, last) {
+            (Some(first), Some(last)) => last - first,
+            _ => Duration::ZERO,
+        };
+        Ok((elapsed, runs.into_iter().map(|r| r.2).collect()))
+    })
+}
+
+fn run_processes(
+    cfg: &ConcurrentConfig,
+    method: Method,
+) -> io::Result<(Duration, Vec<WorkerStats>)> {
+    // children block reading the gate until the parent closes its end
+    let (gate_read, gate_write) = pipe()?;
+    let mut children = Vec::new();
+    let mut error = None;
+    for id in 0..cfg.workers as u64 {
+        let (result_read, result_write) = match pipe() {
+            Ok(fds) => fds,
+            Err(e) => {
+                error = Some(io::Error::from(e));
+                break;
+            }
+        };
+        let forked = match unsafe { fork() } {
+            Ok(forked) => forked,
+            Err(e) => {
+                error = Some(io::Error::from(e));
+                break;
+            }
+        };
+        match forked {
+            ForkResult::Child => {
+                drop(gate_write);
+                drop(result_read);
+                let mut gate = File::from(gate_read);
+                let _ = gate.read(&mut [0u8]);
+                let code = match worker(cfg, method, id) {
+                    Ok(stats) => {
+                        let bytes: Vec<u8> = stats
+                            .to_words()
+                            .iter()
+                            .flat_map(|w| w.to_le_bytes())
+                            .collect();
+                        match File::from(result_write).write_all(&bytes) {
+                            Ok(()) => 0,
+                            Err(_) => 1,
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("worker {}: {}", id, e);
+                        1
+                    }
+                };
+                // skip the parent's atexit handlers and buffered stdout
+                unsafe { libc::_exit(code) };
+            }
+            ForkResult::Parent { child } => {
+                drop(result_write);
+                children.push((child, File::from(result_read)));
+            }
+        }
+    }
+
+    let start = Instant::now();
+    drop(gate_write);
+    if let Some(e) = error {
+        // the children already forked were let go with the gate; don't
+        // leave them behind as zombies or still writing after we return
+        for (child, pipe) in children {
+            drop(pipe);
+            let _ = waitpid(child, None);
+        }
+        return Err(e);
+    }
+    let mut results = Vec::new();
+    for (_, pipe) in &mut children {
+        let mut bytes = Vec::new();
+        pipe.read_to_end(&mut bytes)?;
+        results.push(bytes);
+    }
+    let elapsed = start.elapsed();
+
+    let mut stats = Vec::new();
+    let mut failed = false;
+    for ((child, _), bytes) in children.iter().zip(results) {
+        let status = waitpid(*child, None)?;
+        let words: Vec<u64> = bytes
+            .chunks_exact(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        match (status, WorkerStats::from_words(&words)) {
+            (WaitStatus::Exited(_, 0), Some(s)) => stats.push(s),
+            _ => failed = true,
+        }
+    }
+    if failed {
+        return Err(io::Error::other("a worker process failed"));
+    }
+    Ok((elapsed, stats))
+}
+
+/// Result of reading the file back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Verification {
+    /// Records the workers wrote between them.
+    pub expected: u64,
+    /// Records found whole, with a valid checksum.
+    pub intact: u64,
+    /// Record-sized slots that aren't one record: interleaved or partly
+    /// overwritten writes, or a short tail.
+    pub torn: u64,
+    /// Records never found.
+    pub missing: u64,
+    /// Records found more than once.
+    pub duplicates: u64,
+    /// Records found before an earlier record of the same worker; one
+    /// writer's appends should keep their order.
+    pub reordered: u64,
+}
+
+impl Verification {
+    pub fn is_clean(&self) -> bool {
+        self.intact == self.expected
+            && self.torn == 0
+            && self.missing == 0
+            && self.duplicates == 0
+            && self.reordered == 0
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_clean() {
+            return write!(f, "ok ({} records)", self.intact);
+        }
+        write!(
+            f,
+            "CORRUPT: {}/{} intact, {} torn, {} missing, {} duplicated, {} reordered",
+            self.intact, self.expected, self.torn, self.missing, self.duplicates, self.reordered
+        )
+    }
+}
+
+/// Read `cfg.path` back and check every worker's records against what it
+/// should have written.
+pub fn verify(cfg: &ConcurrentConfig) -> io::Result<Verification> {
+    let expected = cfg.workers as u64 * cfg.records;
+    let mut seen = vec![false; expected as usize];
+    let mut last: Vec<Option<u64>> = vec![None; cfg.workers];
+    let mut v = Verification {
+        expected,
+        ..Default::default()
+    };
+
+    let mut reader = BufReader::with_capacity(1 << 20, File::open(&cfg.path)?);
+    let mut buf = vec![0u8; cfg.record_size];
+    loop {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match reader.read(&mut buf[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+        if filled < buf.len() {
+            v.torn += 1;
+            break;
+        }
+        let parsed = parse_record(&buf)
+            .filter(|&(worker, seq)| worker < cfg.workers as u64 && seq < cfg.records);
+        let Some((worker, seq)) = parsed else {
+            v.torn += 1;
+            continue;
+        };
+        let slot = &mut seen[(worker * cfg.records + seq) as usize];
+        if *slot {
+            v.duplicates += 1;
+            continue;
+        }
+        *slot = true;
+        v.intact += 1;
+        let prev = &mut last[worker as usize];
+        if prev.is_some_and(|p| p > seq) {
+            v.reordered += 1;
+        }
+        *prev = Some(seq);
+    }
+    v.missing = seen.iter().filter(|&&s| !s).count() as u64;
+    Ok(v)
+}
+
+pub struct ConcurrentReport {
+    pub method: Method,
+    pub spawn: Spawn,
+    pub workers: usize,
+    pub bytes: u64,
+    /// From releasing the workers to the last one finishing.
+    pub elapsed: Duration,
+    pub latency: Histogram,
+    pub lock_wait: Histogram,
+    /// Slowest and fastest worker, to show how fair the lock was.
+    pub slowest: Duration,
+    pub fastest: Duration,
+    pub verification: Verification,
+}
+
+impl ConcurrentReport {
+    pub fn throughput_mib(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.bytes as f64 / (1024.0 * 1024.0) / secs
+        }
+    }
+
+    pub fn records_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.latency.count() as f64 / secs
+        }
+    }
+
+    pub fn record(&self, cfg: &ConcurrentConfig) -> Record {
+        let v = &self.verification;
+        Record::new("concurrent_write", &cfg.path)
+            .param("method", self.method.name())
+            .param("spawn", self.spawn.to_string())
+            .param("workers", self.workers)
+            .param("records", cfg.records)
+            .param("record_size", cfg.record_size)
+            .result("bytes", self.bytes)
+            .elapsed(self.elapsed)
+            .result("throughput_mib_s", self.throughput_mib())
+            .result("records_per_s", self.records_per_sec())
+            .latency("latency", &self.latency)
+            .latency("lock_wait", &self.lock_wait)
+            .result("slowest_worker_ns", self.slowest.as_nanos() as u64)
+            .result("fastest_worker_ns", self.fastest.as_nanos() as u64)
+            .result("intact", v.intact)
+            .result("torn", v.torn)
+            .result("missing", v.missing)
+            .result("duplicates", v.duplicates)
+            .result("reordered", v.reordered)
+    }
+}
+
+pub fn run(cfg: &ConcurrentConfig, method: Method) -> io::Result<ConcurrentReport> {
+    if cfg.workers == 0 || cfg.record_size < MIN_RECORD_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "need at least one worker and records of at least {} bytes",
+                MIN_RECORD_SIZE
+            ),
+        ));
+    }
+    if method == Method::Fcntl && cfg.spawn == Spawn::Threads {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "fcntl record locks are per process and don't exclude threads; use processes",
+        ));
+    }
+
+    File::create(&cfg.path)?;
+    let (elapsed, stats) = match cfg.spawn {
+        Spawn::Threads => run_threads(cfg, method)?,
+        Spawn::Processes => run_processes(cfg, method)?,
+    };
+
+    let mut latency = Histogram::new();
+    let mut lock_wait = Histogram::new();
+    for s in &stats {
+        latency.merge(&s.latency);
+        lock_wait.merge(&s.lock_wait);
+    }
+    Ok(ConcurrentReport {
+        method,
+        spawn: cfg.spawn,
+        workers: cfg.workers,
+        bytes: stats.iter().map(|s| s.bytes).sum(),
+        elapsed,
+        latency,
+        lock_wait,
+        slowest: stats.iter().map(|s| s.elapsed).max().unwrap_or_default(),
+        fastest: stats.iter().map(|s| s.elapsed).min().unwrap_or_default(),
+        verification: verify(cfg)?,
+    })
+}
+
+pub fn print_table(reports: &[ConcurrentReport]) {
+    println!(
+        "{:<9} {:>10} {:>9} {:>12} {:>9} {:>9} {:>9} {:>10} {:>10} {:>10}  check",
+        "method",
+        "time",
+        "MiB/s",
+        "records/s",
+        "p50",
+        "p99",
+        "max",
+        "lock p99",
+        "slowest",
+        "fastest"
+    );
+    for r in reports {
+        println!(
+            "{:<9} {:>10} {:>9.1} {:>12.0} {:>9} {:>9} {:>9} {:>10} {:>10} {:>10}  {}",
+            r.method.name(),
+            fmt_nanos(r.elapsed.as_nanos() as u64),
+            r.throughput_mib(),
+            r.records_per_sec(),
+            fmt_nanos(r.latency.percentile(50.0)),
+            fmt_nanos(r.latency.percentile(99.0)),
+            fmt_nanos(r.latency.max()),
+            fmt_nanos(r.lock_wait.percentile(99.0)),
+            fmt_nanos(r.slowest.as_nanos() as u64),
+            fmt_nanos(r.fastest.as_nanos() as u64),
+            r.verification
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use std::io;
+
+use super::{Recorder, WriteConfig, durability::Syncer, preallocate};
+use crate::direct::DirectFile;
+
+// O_DIRECT bypasses the page cache. Buffer address, length and file offset
+// must be aligned to the device's logical block size, so the block size has to
+// be a multiple of it; a padded tail is cut off again with ftruncate.
+pub fn run(cfg: &WriteConfig, rec: &mut Recorder) -> io::Result<()> {
+    let file = DirectFile::create_with_flags(&cfg.path, cfg.durability.open_flags())?;
+    preallocate(cfg, file.file(), rec)?;
+    let mut syncer = Syncer::new(cfg.durability);
+
+    let align = file.alignment();
+    file.check_block_size(cfg.block_size)?;
+    let block = cfg.block_size;
+    let mut chunk = file.buffer(block)?;
+    chunk.fill(b'A');
+
+    let mut offset = 0;
+    while offset < cfg.total_size {
+        let len = (cfg.total_size - offset).min(block as u64) as usize;
+        let padded = len.next_multiple_of(align);
+        rec.op(|| file.write_at(&chunk[..padded], offset))?;
+        offset += len as u64;
+        rec.bytes += len as u64;
+
+        if syncer.wrote(len as u64) {
+            syncer.sync(file.file(), rec)?;
+        }
+    }
+
+    if !cfg.total_size.is_multiple_of(align as u64) {
+        file.set_len(cfg.total_size)?;
+    }
+    // O_DIRECT skips the page cache but not the metadata or the disk's own cache
+    syncer.finish(file.file(), rec)?;
+
+    rec.syscalls += file.syscalls();
+    Ok(())
+}
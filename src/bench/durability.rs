@@ -0,0 +1,163 @@
+use std::{fmt, fs::File, io, os::unix::io::AsRawFd, str::FromStr, time::Instant};
+
+use super::Recorder;
+
+/// How hard a strategy makes sure its data reached stable storage.
+///
+/// Without one of these a "write" only means the page cache has the data,
+/// which is why plain buffered numbers look so good next to O_DIRECT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Nothing beyond write(2); data may still be only in the page cache.
+    #[default]
+    None,
+    /// One fsync(2) at the end.
+    Fsync,
+    /// fsync(2) each time this many more bytes were written, and at the end.
+    FsyncEvery(u64),
+    /// One fdatasync(2) at the end (skips metadata that isn't needed to read the data back).
+    Fdatasync,
+    /// Open with O_SYNC: every write returns only once data and metadata are stable.
+    OSync,
+    /// Open with O_DSYNC: like O_SYNC, for data only.
+    ODsync,
+    /// sync_file_range(2) over what was written since the last call, every this many bytes
+    /// and at the end. Flushes data pages only: no metadata, no disk cache flush.
+    SyncFileRange(u64),
+}
+
+impl Durability {
+    pub const EXAMPLES: &'static str =
+        "none, fsync, fsync-every=BYTES, fdatasync, osync, odsync, sync-file-range[=BYTES]";
+
+    /// Extra open(2) flags this mode needs.
+    pub fn open_flags(self) -> i32 {
+        match self {
+            Durability::OSync => libc::O_SYNC,
+            Durability::ODsync => libc::O_DSYNC,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Durability::None => write!(f, "none"),
+            Durability::Fsync => write!(f, "fsync"),
+            Durability::FsyncEvery(n) => write!(f, "fsync-every={}", n),
+            Durability::Fdatasync => write!(f, "fdatasync"),
+            Durability::OSync => write!(f, "osync"),
+            Durability::ODsync => write!(f, "odsync"),
+            Durability::SyncFileRange(0) => write!(f, "sync-file-range"),
+            Durability::SyncFileRange(n) => write!(f, "sync-file-range={}", n),
+        }
+    }
+}
+
+impl FromStr for Durability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.split_once('=') {
+            Some((name, arg)) => (name, Some(crate::cli::parse_size(arg)?)),
+            None => (s, None),
+        };
+        match (name, arg) {
+            ("none", None) => Ok(Durability::None),
+            ("fsync", None) => Ok(Durability::Fsync),
+            ("fsync-every", Some(n)) if n > 0 => Ok(Durability::FsyncEvery(n)),
+            ("fdatasync", None) => Ok(Durability::Fdatasync),
+            ("osync", None) => Ok(Durability::OSync),
+            ("odsync", None) => Ok(Durability::ODsync),
+            ("sync-file-range", n) => Ok(Durability::SyncFileRange(n.unwrap_or(0))),
+            _ => Err(format!(
+                "unknown durability mode '{}' (expected one of: {})",
+                s,
+                Durability::EXAMPLES
+            )),
+        }
+    }
+}
+
+/// Applies a [`Durability`] mode while a strategy writes sequentially.
+///
+/// Strategies call [`Syncer::wrote`] after each block and, when it returns
+/// true, make sure their own buffers are in the kernel before [`Syncer::sync`].
+/// [`Syncer::finish`] does whatever the mode wants at the end.
+pub struct Syncer {
+    mode: Durability,
+    written: u64,
+    synced: u64,
+}
+
+impl Syncer {
+    pub fn new(mode: Durability) -> Self {
+        Self {
+            mode,
+            written: 0,
+            synced: 0,
+        }
+    }
+
+    /// Account for `len` more bytes; true if a periodic sync is due now.
+    pub fn wrote(&mut self, len: u64) -> bool {
+        self.written += len;
+        match self.mode {
+            Durability::FsyncEvery(n) | Durability::SyncFileRange(n) if n > 0 => {
+                self.written - self.synced >= n
+            }
+            _ => false,
+        }
+    }
+
+    /// Periodic sync of everything written so far.
+    pub fn sync(&mut self, file: &File, rec: &mut Recorder) -> io::Result<()> {
+        match self.mode {
+            Durability::FsyncEvery(_) => timed(rec, || file.sync_all())?,
+            Durability::SyncFileRange(_) => {
+                let (start, len) = (self.synced, self.written - self.synced);
+                timed(rec, || sync_file_range(file, start, len))?
+            }
+            _ => return Ok(()),
+        }
+        self.synced = self.written;
+        Ok(())
+    }
+
+    /// End-of-run sync.
+    pub fn finish(&mut self, file: &File, rec: &mut Recorder) -> io::Result<()> {
+        match self.mode {
+            Durability::Fsync | Durability::FsyncEvery(_) if self.synced < self.written => {
+                timed(rec, || file.sync_all())?
+            }
+            Durability::Fdatasync => timed(rec, || file.sync_data())?,
+            Durability::SyncFileRange(_) if self.synced < self.written => {
+                return self.sync(file, rec);
+            }
+            _ => return Ok(()),
+        }
+        self.synced = self.written;
+        Ok(())
+    }
+}
+
+fn timed(rec: &mut Recorder, f: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+    let start = Instant::now();
+    let result = f();
+    rec.sync_time += start.elapsed();
+    rec.syncs += 1;
+    rec.syscalls += 1;
+    result
+}
+
+fn sync_file_range(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    let flags = libc::SYNC_FILE_RANGE_WAIT_BEFORE
+        | libc::SYNC_FILE_RANGE_WRITE
+        | libc::SYNC_FILE_RANGE_WAIT_AFTER;
+    let rc = unsafe { libc::sync_file_range(file.as_raw_fd(), offset as i64, len as i64, flags) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
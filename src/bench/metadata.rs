@@ -0,0 +1,314 @@
+// Metadata operations on a populated directory, one latency sample per call:
+//
+//   stat        fstatat(2) every file
+//   open        openat(2) O_RDONLY and close every file
+//   rename      renameat(2) every file to a new name in the same directory
+//   rename-dir  renameat(2) every file into a sibling directory
+//   link        linkat(2) a second name for every file
+//   symlink     symlinkat(2) a symlink to every file
+//   readdir     list the whole directory (one sample per pass)
+//   utimens     utimensat(2) every file to now
+//   chmod       fchmodat(2) every file
+//
+// The files are created once, untimed. Operations that add or move names
+// undo that afterwards, also untimed, so every operation sees the same
+// directory.
+
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use super::lookup;
+use crate::{
+    dir::Dir,
+    histogram::{Histogram, fmt_nanos},
+    names::Scheme,
+    report::Record,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Stat,
+    Open,
+    Rename,
+    RenameDir,
+    Link,
+    Symlink,
+    Readdir,
+    Utimens,
+    Chmod,
+}
+
+impl Op {
+    pub const ALL: [Op; 9] = [
+        Op::Stat,
+        Op::Open,
+        Op::Rename,
+        Op::RenameDir,
+        Op::Link,
+        Op::Symlink,
+        Op::Readdir,
+        Op::Utimens,
+        Op::Chmod,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Stat => "stat",
+            Op::Open => "open",
+            Op::Rename => "rename",
+            Op::RenameDir => "rename-dir",
+            Op::Link => "link",
+            Op::Symlink => "symlink",
+            Op::Readdir => "readdir",
+            Op::Utimens => "utimens",
+            Op::Chmod => "chmod",
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Op {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lookup(&Op::ALL, s, |op| op.name(), "metadata operation")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MetadataConfig {
+    /// Created for the run and removed afterwards; must not exist.
+    pub dir: PathBuf,
+    pub files: usize,
+    pub scheme: Scheme,
+    pub seed: u64,
+    /// How many times readdir lists the directory.
+    pub readdir_passes: u32,
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("metadata_bench.d"),
+            files: 10_000,
+            scheme: Scheme::Random,
+            seed: 0,
+            readdir_passes: 10,
+        }
+    }
+}
+
+// The sibling directory rename-dir moves files into.
+const OTHER: &str = "other";
+
+/// The populated directory the operations run against.
+pub struct Fixture {
+    dir: Dir,
+    other: Dir,
+    names: Vec<String>,
+    subdirs: Vec<String>,
+    path: PathBuf,
+}
+
+impl Fixture {
+    /// Create `cfg.dir` with `cfg.files` one-byte files in it.
+    pub fn create(cfg: &MetadataConfig) -> io::Result<Self> {
+        fs::create_dir(&cfg.dir)?;
+        let mut fixture = Self {
+            dir: Dir::open(&cfg.dir)?,
+            other: Dir::open(&cfg.dir)?,
+            names: Vec::new(),
+            subdirs: cfg.scheme.dirs(),
+            path: cfg.dir.clone(),
+        };
+        // from here on, dropping the fixture cleans up whatever was made
+        fixture.dir.mkdir(OTHER)?;
+        fixture.other = fixture.dir.open_dir(OTHER)?;
+        for sub in &fixture.subdirs {
+            fixture.dir.mkdir(sub)?;
+        }
+        for name in cfg.scheme.generate(cfg.files, cfg.seed) {
+            fixture.dir.create(&name)?.write_all(b"m")?;
+            fixture.names.push(name);
+        }
+        Ok(fixture)
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        // an operation that failed halfway may have left any of its names
+        for name in &self.names {
+            let _ = self.dir.unlink(name);
+            for suffix in [".renamed", ".link", ".symlink"] {
+                let _ = self.dir.unlink(&format!("{}{}", name, suffix));
+            }
+            let _ = self.other.unlink(&flat(name));
+        }
+        for sub in &self.subdirs {
+            let _ = self.dir.rmdir(sub);
+        }
+        let _ = self.dir.rmdir(OTHER);
+        let _ = fs::remove_dir(&self.path);
+    }
+}
+
+// Names in the other directory are flat, whatever the scheme.
+fn flat(name: &str) -> String {
+    name.replace('/', "_")
+}
+
+pub struct MetadataReport {
+    pub op: Op,
+    pub elapsed: Duration,
+    pub latency: Histogram,
+}
+
+impl MetadataReport {
+    pub fn ops_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.latency.count() as f64 / secs
+        }
+    }
+
+    pub fn record(&self, cfg: &MetadataConfig) -> Record {
+        Record::new("metadata_bench", &cfg.dir)
+            .param("op", self.op.name())
+            .param("files", cfg.files)
+            .param("scheme", cfg.scheme.to_string())
+            .param("seed", cfg.seed)
+            .param("readdir_passes", cfg.readdir_passes)
+            .result("ops", self.latency.count())
+            .elapsed(self.elapsed)
+            .result("ops_per_s", self.ops_per_sec())
+            .latency("latency", &self.latency)
+    }
+}
+
+/// Time `op` once over every file of `fx` (or `cfg.readdir_passes` listings).
+pub fn run(op: Op, cfg: &MetadataConfig, fx: &Fixture) -> io::Result<MetadataReport> {
+    let mut latency = Histogram::new();
+    let mut timed = |f: &mut dyn FnMut() -> io::Result<()>| -> io::Result<()> {
+        let start = Instant::now();
+        f()?;
+        latency.record_duration(start.elapsed());
+        Ok(())
+    };
+    let dir = &fx.dir;
+
+    let start = Instant::now();
+    match op {
+        Op::Stat => {
+            for name in &fx.names {
+                timed(&mut || dir.stat(name).map(drop))?;
+            }
+        }
+        Op::Open => {
+            for name in &fx.names {
+                timed(&mut || dir.open_file(name, libc::O_RDONLY).map(drop))?;
+            }
+        }
+        Op::Rename => {
+            for name in &fx.names {
+                let to = format!("{}.renamed", name);
+                timed(&mut || dir.rename(name, dir, &to))?;
+            }
+        }
+        Op::RenameDir => {
+            for name in &fx.names {
+                let to = flat(name);
+                timed(&mut || dir.rename(name, &fx.other, &to))?;
+            }
+        }
+        Op::Link => {
+            for name in &fx.names {
+                let to = format!("{}.link", name);
+                timed(&mut || dir.link(name, dir, &to))?;
+            }
+        }
+        Op::Symlink => {
+            for name in &fx.names {
+                let link = format!("{}.symlink", name);
+                timed(&mut || dir.symlink(name, &link))?;
+            }
+        }
+        Op::Readdir => {
+            for _ in 0..cfg.readdir_passes {
+                timed(&mut || dir.count_entries().map(drop))?;
+            }
+        }
+        Op::Utimens => {
+            for name in &fx.names {
+                timed(&mut || dir.touch(name))?;
+            }
+        }
+        Op::Chmod => {
+            for name in &fx.names {
+                timed(&mut || dir.chmod(name, 0o600))?;
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+    restore(op, fx)?;
+
+    Ok(MetadataReport {
+        op,
+        elapsed,
+        latency,
+    })
+}
+
+// Put the directory back the way Fixture::create left it.
+fn restore(op: Op, fx: &Fixture) -> io::Result<()> {
+    let dir = &fx.dir;
+    for name in &fx.names {
+        match op {
+            Op::Rename => dir.rename(&format!("{}.renamed", name), dir, name)?,
+            Op::RenameDir => fx.other.rename(&flat(name), dir, name)?,
+            Op::Link => dir.unlink(&format!("{}.link", name))?,
+            Op::Symlink => dir.unlink(&format!("{}.symlink", name))?,
+            Op::Chmod => dir.chmod(name, 0o644)?,
+            _ => return Ok(()),
+        }
+    }
+    Ok(())
+}
+
+pub fn print_table(reports: &[MetadataReport]) {
+    println!(
+        "{:<11} {:>9} {:>10} {:>11} {:>9} {:>9} {:>9} {:>9} {:>9}",
+        "op", "ops", "time", "ops/s", "mean", "p50", "p90", "p99", "max"
+    );
+    for r in reports {
+        let h = &r.latency;
+        println!(
+            "{:<11} {:>9} {:>10} {:>11.0} {:>9} {:>9} {:>9} {:>9} {:>9}",
+            r.op.name(),
+            h.count(),
+            fmt_nanos(r.elapsed.as_nanos() as u64),
+            r.ops_per_sec(),
+            fmt_nanos(h.mean() as u64),
+            fmt_nanos(h.percentile(50.0)),
+            fmt_nanos(h.percentile(90.0)),
+            fmt_nanos(h.percentile(99.0)),
+            fmt_nanos(h.max()),
+        );
+    }
+}
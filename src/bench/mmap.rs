@@ -0,0 +1,86 @@
+use std::{fs::OpenOptions, io, time::Instant};
+
+use super::{Recorder, WriteConfig, durability::Syncer, preallocate};
+use crate::{
+    mmap::{Advice, Mapping, Msync},
+    sparse::Prealloc,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct MmapConfig {
+    /// How to flush the mapping once everything was written.
+    pub msync: Msync,
+    /// madvise hint applied right after mapping.
+    pub advice: Option<Advice>,
+}
+
+// Store through a MAP_SHARED mapping instead of calling write(2). The file is
+// grown to its final size first: with --prealloc allocate by fallocate, else
+// by ftruncate, so without preallocation every page gets its block on the
+// fault path. After that the only kernel entries are page faults (see the
+// minflt/majflt columns) and the msync.
+pub fn run(cfg: &WriteConfig, rec: &mut Recorder) -> io::Result<()> {
+    if cfg.durability.open_flags() != 0 {
+        // stores never go through write(2), which is all O_SYNC/O_DSYNC affect
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} has no effect on stores through a mapping",
+                cfg.durability
+            ),
+        ));
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&cfg.path)?;
+    if cfg.total_size == 0 {
+        return Ok(());
+    }
+
+    preallocate(cfg, &file, rec)?;
+    if cfg.prealloc != Prealloc::Allocate {
+        file.set_len(cfg.total_size)?;
+        rec.syscalls += 1;
+    }
+    let mut map = Mapping::read_write(&file, cfg.total_size as usize)?;
+    rec.syscalls += 1;
+    if let Some(advice) = cfg.mmap.advice {
+        map.advise(advice)?;
+        rec.syscalls += 1;
+    }
+
+    // Durability modes that rely on fsync & co. work on mappings too.
+    let mut syncer = Syncer::new(cfg.durability);
+    let chunk = vec![b'A'; cfg.block_size];
+
+    let mut offset = 0;
+    for i in 0..cfg.blocks() {
+        let len = cfg.block_len(i);
+        rec.op(|| {
+            map[offset..offset + len].copy_from_slice(&chunk[..len]);
+            Ok::<_, io::Error>(())
+        })?;
+        offset += len;
+        rec.bytes += len as u64;
+
+        if syncer.wrote(len as u64) {
+            syncer.sync(&file, rec)?;
+        }
+    }
+
+    if cfg.mmap.msync != Msync::None {
+        let start = Instant::now();
+        map.flush(cfg.mmap.msync)?;
+        rec.sync_time += start.elapsed();
+        rec.syncs += 1;
+        rec.syscalls += 1;
+    }
+    syncer.finish(&file, rec)?;
+
+    drop(map);
+    rec.syscalls += 1;
+    Ok(())
+}
@@ -0,0 +1,320 @@
+// Write benchmark harness shared by buffered_write, unbuffered_write, direct_IO,
+// the io_uring backend, vectored writes and mmap.
+//
+// Every strategy writes the same workload (total size in blocks of block_size
+// bytes of 'A') to the same path, so the numbers line up side by side.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    histogram::{Histogram, fmt_nanos},
+    report::Record,
+    sparse::Prealloc,
+};
+
+pub use durability::Durability;
+
+pub mod buffered;
+pub mod concurrent;
+pub mod direct;
+pub mod durability;
+pub mod metadata;
+pub mod mmap;
+pub mod read;
+pub mod unbuffered;
+pub mod uring;
+pub mod vectored;
+pub mod workload;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    Buffered,
+    Unbuffered,
+    Direct,
+    Uring,
+    Vectored,
+    Mmap,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 6] = [
+        Strategy::Buffered,
+        Strategy::Unbuffered,
+        Strategy::Direct,
+        Strategy::Uring,
+        Strategy::Vectored,
+        Strategy::Mmap,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Buffered => "buffered",
+            Strategy::Unbuffered => "unbuffered",
+            Strategy::Direct => "direct",
+            Strategy::Uring => "uring",
+            Strategy::Vectored => "vectored",
+            Strategy::Mmap => "mmap",
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lookup(&Strategy::ALL, s, |st| st.name(), "strategy")
+    }
+}
+
+/// Find the entry of `all` called `s`, or explain which names are valid.
+pub(crate) fn lookup<T: Copy>(
+    all: &[T],
+    s: &str,
+    name: impl Fn(T) -> &'static str,
+    what: &str,
+) -> Result<T, String> {
+    all.iter().copied().find(|&t| name(t) == s).ok_or_else(|| {
+        let names: Vec<_> = all.iter().map(|&t| name(t)).collect();
+        format!(
+            "unknown {} '{}' (expected one of: {})",
+            what,
+            s,
+            names.join(", ")
+        )
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct WriteConfig {
+    pub path: PathBuf,
+    /// Total bytes to write.
+    pub total_size: u64,
+    /// Bytes handed to each write call by the application.
+    pub block_size: usize,
+    pub durability: Durability,
+    /// fallocate done right after opening, before the first write.
+    pub prealloc: Prealloc,
+    pub uring: uring::UringConfig,
+    pub vectored: vectored::VectoredConfig,
+    pub mmap: mmap::MmapConfig,
+}
+
+impl Default for WriteConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("bench.txt"),
+            total_size: 8 << 20,
+            block_size: 4096,
+            durability: Durability::None,
+            prealloc: Prealloc::None,
+            uring: uring::UringConfig::default(),
+            vectored: vectored::VectoredConfig::default(),
+            mmap: mmap::MmapConfig::default(),
+        }
+    }
+}
+
+impl WriteConfig {
+    /// Number of application-level writes, rounding a partial last block up.
+    pub fn blocks(&self) -> u64 {
+        self.total_size.div_ceil(self.block_size as u64)
+    }
+
+    /// Length of block `i`; only the last one may be short.
+    pub fn block_len(&self, i: u64) -> usize {
+        let done = i * self.block_size as u64;
+        (self.total_size - done).min(self.block_size as u64) as usize
+    }
+}
+
+/// Apply `cfg.prealloc` to a freshly opened (and truncated) file.
+fn preallocate(cfg: &WriteConfig, file: &File, rec: &mut Recorder) -> io::Result<()> {
+    rec.syscalls += cfg.prealloc.apply(file, cfg.total_size)?;
+    Ok(())
+}
+
+/// Collects what a strategy did while it ran.
+#[derive(Default)]
+pub struct Recorder {
+    pub bytes: u64,
+    pub syscalls: u64,
+    pub latency: Histogram,
+    /// Time spent in fsync/fdatasync/sync_file_range, and how many calls.
+    pub sync_time: Duration,
+    pub syncs: u64,
+}
+
+impl Recorder {
+    /// Time one application-level operation and add it to the latency histogram.
+    pub fn op<T, E>(&mut self, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let start = Instant::now();
+        let result = f();
+        self.latency.record_duration(start.elapsed());
+        result
+    }
+}
+
+/// `Write` adapter counting how many times the kernel was actually asked to write.
+pub struct Counted<W> {
+    inner: W,
+    pub calls: u64,
+}
+
+impl<W> Counted<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, calls: 0 }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.calls += 1;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct WriteReport {
+    pub strategy: Strategy,
+    pub durability: Durability,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub syscalls: u64,
+    pub latency: Histogram,
+    pub sync_time: Duration,
+    pub syncs: u64,
+    /// Page faults taken while the strategy ran (getrusage).
+    pub minor_faults: u64,
+    pub major_faults: u64,
+}
+
+impl WriteReport {
+    pub fn throughput_mib(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.bytes as f64 / (1024.0 * 1024.0) / secs
+        }
+    }
+
+    /// This row as a structured record, with the settings it ran under.
+    pub fn record(&self, cfg: &WriteConfig) -> Record {
+        // every strategy's knobs, so CSV rows of different strategies line up
+        Record::new("write_bench", &cfg.path)
+            .param("strategy", self.strategy.name())
+            .param("durability", self.durability.to_string())
+            .param("size", cfg.total_size)
+            .param("block_size", cfg.block_size)
+            .param("prealloc", cfg.prealloc.to_string())
+            .param("queue_depth", cfg.uring.queue_depth)
+            .param("register_buffers", cfg.uring.register_buffers)
+            .param("fixed_files", cfg.uring.fixed_files)
+            .param("iov_batch", cfg.vectored.batch)
+            .param("rwf_flags", cfg.vectored.flags)
+            .param("msync", cfg.mmap.msync.to_string())
+            .param("madvise", cfg.mmap.advice.map(|a| a.to_string()))
+            .result("bytes", self.bytes)
+            .elapsed(self.elapsed)
+            .result("throughput_mib_s", self.throughput_mib())
+            .result("syscalls", self.syscalls)
+            .result("sync_ns", self.sync_time.as_nanos() as u64)
+            .result("syncs", self.syncs)
+            .latency("latency", &self.latency)
+            .result("minor_faults", self.minor_faults)
+            .result("major_faults", self.major_faults)
+    }
+}
+
+pub fn run(strategy: Strategy, cfg: &WriteConfig) -> io::Result<WriteReport> {
+    if cfg.block_size == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "block size must be non-zero",
+        ));
+    }
+
+    let mut rec = Recorder::default();
+    let faults_before = crate::mmap::page_faults();
+    let start = Instant::now();
+    match strategy {
+        Strategy::Buffered => buffered::run(cfg, &mut rec)?,
+        Strategy::Unbuffered => unbuffered::run(cfg, &mut rec)?,
+        Strategy::Direct => direct::run(cfg, &mut rec)?,
+        Strategy::Uring => uring::run(cfg, &mut rec)?,
+        Strategy::Vectored => vectored::run(cfg, &mut rec)?,
+        Strategy::Mmap => mmap::run(cfg, &mut rec)?,
+    }
+    let elapsed = start.elapsed();
+    let faults_after = crate::mmap::page_faults();
+
+    Ok(WriteReport {
+        strategy,
+        durability: cfg.durability,
+        bytes: rec.bytes,
+        elapsed,
+        syscalls: rec.syscalls,
+        latency: rec.latency,
+        sync_time: rec.sync_time,
+        syncs: rec.syncs,
+        minor_faults: faults_after.0 - faults_before.0,
+        major_faults: faults_after.1 - faults_before.1,
+    })
+}
+
+pub fn print_table(reports: &[WriteReport]) {
+    println!(
+        "{:<12} {:<24} {:>12} {:>10} {:>10} {:>12} {:>10} {:>9} {:>9} {:>9} {:>9} {:>8} {:>8}",
+        "strategy",
+        "durability",
+        "bytes",
+        "time",
+        "MiB/s",
+        "syscalls",
+        "sync",
+        "p50",
+        "p90",
+        "p99",
+        "max",
+        "minflt",
+        "majflt"
+    );
+    for r in reports {
+        println!(
+            "{:<12} {:<24} {:>12} {:>10} {:>10.1} {:>12} {:>10} {:>9} {:>9} {:>9} {:>9} {:>8} {:>8}",
+            r.strategy.name(),
+            r.durability.to_string(),
+            r.bytes,
+            fmt_nanos(r.elapsed.as_nanos() as u64),
+            r.throughput_mib(),
+            r.syscalls,
+            fmt_nanos(r.sync_time.as_nanos() as u64),
+            fmt_nanos(r.latency.percentile(50.0)),
+            fmt_nanos(r.latency.percentile(90.0)),
+            fmt_nanos(r.latency.percentile(99.0)),
+            fmt_nanos(r.latency.max()),
+            r.minor_faults,
+            r.major_faults,
+        );
+    }
+}
@@ -0,0 +1,415 @@
+// Read-side counterpart of the write benchmark: read the file a write
+// strategy produced back with each read strategy, from a cold and from a
+// warm page cache.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use super::{Recorder, lookup};
+use crate::{
+    cache::{self, Eviction},
+    direct::DirectFile,
+    histogram::{Histogram, fmt_nanos},
+    mmap::{self, Advice, Mapping},
+    report::Record,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadStrategy {
+    /// BufReader with its default capacity in front of the file.
+    Buffered,
+    /// read(2) straight into a block_size buffer.
+    Raw,
+    /// O_DIRECT reads through an aligned buffer.
+    Direct,
+    /// Walk a read-only mapping block by block.
+    Mmap,
+}
+
+impl ReadStrategy {
+    pub const ALL: [ReadStrategy; 4] = [
+        ReadStrategy::Buffered,
+        ReadStrategy::Raw,
+        ReadStrategy::Direct,
+        ReadStrategy::Mmap,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ReadStrategy::Buffered => "buffered",
+            ReadStrategy::Raw => "raw",
+            ReadStrategy::Direct => "direct",
+            ReadStrategy::Mmap => "mmap",
+        }
+    }
+}
+
+impl fmt::Display for ReadStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ReadStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lookup(&ReadStrategy::ALL, s, |st| st.name(), "read strategy")
+    }
+}
+
+/// Access-pattern hint given to the kernel before reading.
+/// For mmap it becomes the matching madvise; O_DIRECT ignores it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Hint {
+    #[default]
+    None,
+    Sequential,
+    Random,
+    WillNeed,
+    NoReuse,
+    /// readahead(2) of the whole file up front.
+    Readahead,
+}
+
+impl Hint {
+    pub const ALL: [Hint; 6] = [
+        Hint::None,
+        Hint::Sequential,
+        Hint::Random,
+        Hint::WillNeed,
+        Hint::NoReuse,
+        Hint::Readahead,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hint::None => "none",
+            Hint::Sequential => "sequential",
+            Hint::Random => "random",
+            Hint::WillNeed => "willneed",
+            Hint::NoReuse => "noreuse",
+            Hint::Readahead => "readahead",
+        }
+    }
+
+    fn apply(self, file: &File, len: u64) -> io::Result<u64> {
+        let advice = match self {
+            Hint::None => return Ok(0),
+            Hint::Readahead => {
+                cache::readahead(file, 0, len)?;
+                return Ok(1);
+            }
+            Hint::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            Hint::Random => libc::POSIX_FADV_RANDOM,
+            Hint::WillNeed => libc::POSIX_FADV_WILLNEED,
+            Hint::NoReuse => libc::POSIX_FADV_NOREUSE,
+        };
+        cache::fadvise(file, 0, 0, advice)?;
+        Ok(1)
+    }
+
+    fn madvise(self) -> Option<Advice> {
+        match self {
+            Hint::Sequential => Some(Advice::Sequential),
+            Hint::Random => Some(Advice::Random),
+            Hint::WillNeed | Hint::Readahead => Some(Advice::WillNeed),
+            Hint::None | Hint::NoReuse => None,
+        }
+    }
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Hint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lookup(&Hint::ALL, s, |h| h.name(), "hint")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheState {
+    /// File evicted from the page cache before the run.
+    Cold,
+    /// File read once, untimed, before the run.
+    Warm,
+}
+
+impl CacheState {
+    pub fn name(self) -> &'static str {
+        match self {
+            CacheState::Cold => "cold",
+            CacheState::Warm => "warm",
+        }
+    }
+}
+
+impl FromStr for CacheState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lookup(
+            &[CacheState::Cold, CacheState::Warm],
+            s,
+            |c| c.name(),
+            "cache state",
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReadConfig {
+    pub path: PathBuf,
+    /// Bytes asked for by each application-level read.
+    pub block_size: usize,
+    pub hint: Hint,
+}
+
+impl Default for ReadConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("bench.txt"),
+            block_size: 4096,
+            hint: Hint::None,
+        }
+    }
+}
+
+pub struct ReadReport {
+    pub strategy: ReadStrategy,
+    pub cache: CacheState,
+    /// How the cache was emptied for a cold run.
+    pub eviction: Option<Eviction>,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub syscalls: u64,
+    pub latency: Histogram,
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    /// Sum of all bytes read, to check every strategy saw the same data.
+    pub checksum: u64,
+}
+
+impl ReadReport {
+    pub fn throughput_mib(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.bytes as f64 / (1024.0 * 1024.0) / secs
+        }
+    }
+
+    pub fn record(&self, cfg: &ReadConfig) -> Record {
+        Record::new("read_bench", &cfg.path)
+            .param("strategy", self.strategy.name())
+            .param("cache", self.cache.name())
+            .param("block_size", cfg.block_size)
+            .param("hint", cfg.hint.name())
+            .result("eviction", self.eviction.map(|e| format!("{:?}", e)))
+            .result("bytes", self.bytes)
+            .elapsed(self.elapsed)
+            .result("throughput_mib_s", self.throughput_mib())
+            .result("syscalls", self.syscalls)
+            .latency("latency", &self.latency)
+            .result("minor_faults", self.minor_faults)
+            .result("major_faults", self.major_faults)
+            .result("checksum", self.checksum)
+    }
+}
+
+pub fn run(strategy: ReadStrategy, cache: CacheState, cfg: &ReadConfig) -> io::Result<ReadReport> {
+    if cfg.block_size == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "block size must be non-zero",
+        ));
+    }
+
+    let eviction = match cache {
+        CacheState::Cold => Some(cache::evict(&cfg.path)?),
+        CacheState::Warm => {
+            // any untimed pass will do; raw reads keep it cheap
+            read_raw(cfg, &mut Recorder::default())?;
+            None
+        }
+    };
+
+    let mut rec = Recorder::default();
+    let faults_before = mmap::page_faults();
+    let start = Instant::now();
+    let checksum = match strategy {
+        ReadStrategy::Buffered => read_buffered(cfg, &mut rec)?,
+        ReadStrategy::Raw => read_raw(cfg, &mut rec)?,
+        ReadStrategy::Direct => read_direct(cfg, &mut rec)?,
+        ReadStrategy::Mmap => read_mmap(cfg, &mut rec)?,
+    };
+    let elapsed = start.elapsed();
+    let faults_after = mmap::page_faults();
+
+    Ok(ReadReport {
+        strategy,
+        cache,
+        eviction,
+        bytes: rec.bytes,
+        elapsed,
+        syscalls: rec.syscalls,
+        latency: rec.latency,
+        minor_faults: faults_after.0 - faults_before.0,
+        major_faults: faults_after.1 - faults_before.1,
+        checksum,
+    })
+}
+
+fn sum(acc: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(acc, |acc, &b| acc.wrapping_add(b as u64))
+}
+
+// read() until EOF, retrying EINTR; returns the checksum.
+fn drain(reader: &mut impl Read, buf: &mut [u8], rec: &mut Recorder) -> io::Result<u64> {
+    let mut checksum = 0;
+    loop {
+        let n = match rec.op(|| reader.read(buf)) {
+            Ok(0) => return Ok(checksum),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        checksum = sum(checksum, &buf[..n]);
+        rec.bytes += n as u64;
+    }
+}
+
+fn open_hinted(cfg: &ReadConfig, rec: &mut Recorder) -> io::Result<File> {
+    let file = File::open(&cfg.path)?;
+    let len = file.metadata()?.len();
+    rec.syscalls += 1 + cfg.hint.apply(&file, len)?;
+    Ok(file)
+}
+
+// `read` adapter counting the calls that reach the kernel.
+struct CountedReader<R> {
+    inner: R,
+    calls: u64,
+}
+
+impl<R: Read> Read for CountedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.calls += 1;
+        self.inner.read(buf)
+    }
+}
+
+fn read_buffered(cfg: &ReadConfig, rec: &mut Recorder) -> io::Result<u64> {
+    let file = open_hinted(cfg, rec)?;
+    let mut reader = BufReader::new(CountedReader {
+        inner: file,
+        calls: 0,
+    });
+    let mut buf = vec![0u8; cfg.block_size];
+    let checksum = drain(&mut reader, &mut buf, rec)?;
+    rec.syscalls += reader.get_ref().calls;
+    Ok(checksum)
+}
+
+fn read_raw(cfg: &ReadConfig, rec: &mut Recorder) -> io::Result<u64> {
+    let mut file = CountedReader {
+        inner: open_hinted(cfg, rec)?,
+        calls: 0,
+    };
+    let mut buf = vec![0u8; cfg.block_size];
+    let checksum = drain(&mut file, &mut buf, rec)?;
+    rec.syscalls += file.calls;
+    Ok(checksum)
+}
+
+fn read_direct(cfg: &ReadConfig, rec: &mut Recorder) -> io::Result<u64> {
+    let file = DirectFile::open(&cfg.path)?;
+    file.check_block_size(cfg.block_size)?;
+    let block = cfg.block_size;
+    let mut buf = file.buffer(block)?;
+
+    let mut checksum = 0;
+    let mut offset = 0;
+    loop {
+        let n = rec.op(|| file.read_at(&mut buf, offset))?;
+        checksum = sum(checksum, &buf[..n]);
+        rec.bytes += n as u64;
+        offset += n as u64;
+        if n < block {
+            break;
+        }
+    }
+    rec.syscalls += 1 + file.syscalls();
+    Ok(checksum)
+}
+
+fn read_mmap(cfg: &ReadConfig, rec: &mut Recorder) -> io::Result<u64> {
+    let file = File::open(&cfg.path)?;
+    let len = file.metadata()?.len() as usize;
+    rec.syscalls += 1;
+    if len == 0 {
+        return Ok(0);
+    }
+
+    let map = Mapping::read_only(&file, len)?;
+    rec.syscalls += 2; // mmap + munmap
+    if let Some(advice) = cfg.hint.madvise() {
+        map.advise(advice)?;
+        rec.syscalls += 1;
+    }
+
+    let mut checksum = 0;
+    for chunk in map.chunks(cfg.block_size) {
+        checksum = rec.op(|| Ok::<_, io::Error>(sum(checksum, chunk)))?;
+        rec.bytes += chunk.len() as u64;
+    }
+    Ok(checksum)
+}
+
+pub fn print_table(reports: &[ReadReport]) {
+    println!(
+        "{:<10} {:<6} {:>12} {:>10} {:>10} {:>10} {:>9} {:>9} {:>9} {:>8} {:>8} {:>18}",
+        "strategy",
+        "cache",
+        "bytes",
+        "time",
+        "MiB/s",
+        "syscalls",
+        "p50",
+        "p99",
+        "max",
+        "minflt",
+        "majflt",
+        "checksum"
+    );
+    for r in reports {
+        println!(
+            "{:<10} {:<6} {:>12} {:>10} {:>10.1} {:>10} {:>9} {:>9} {:>9} {:>8} {:>8} {:>18x}",
+            r.strategy.name(),
+            r.cache.name(),
+            r.bytes,
+            fmt_nanos(r.elapsed.as_nanos() as u64),
+            r.throughput_mib(),
+            r.syscalls,
+            fmt_nanos(r.latency.percentile(50.0)),
+            fmt_nanos(r.latency.percentile(99.0)),
+            fmt_nanos(r.latency.max()),
+            r.minor_faults,
+            r.major_faults,
+            r.checksum,
+        );
+    }
+}
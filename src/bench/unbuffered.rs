@@ -0,0 +1,33 @@
+use std::{fs::OpenOptions, io::Write, os::unix::fs::OpenOptionsExt};
+
+use super::{Counted, Recorder, WriteConfig, durability::Syncer, preallocate};
+
+// Every application write goes straight to write(2). With a block size of 1
+// this is the original one-byte-at-a-time syscall overhead demo.
+pub fn run(cfg: &WriteConfig, rec: &mut Recorder) -> std::io::Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .custom_flags(cfg.durability.open_flags())
+        .open(&cfg.path)?;
+    preallocate(cfg, &file, rec)?;
+    let mut file = Counted::new(file);
+    let mut syncer = Syncer::new(cfg.durability);
+
+    let chunk = vec![b'A'; cfg.block_size];
+
+    for i in 0..cfg.blocks() {
+        let len = cfg.block_len(i);
+        rec.op(|| file.write_all(&chunk[..len]))?;
+        rec.bytes += len as u64;
+
+        if syncer.wrote(len as u64) {
+            syncer.sync(file.get_ref(), rec)?;
+        }
+    }
+
+    syncer.finish(file.get_ref(), rec)?;
+    rec.syscalls += file.calls;
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io,
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    time::Instant,
+};
+
+use io_uring::{IoUring, opcode, squeue, types};
+
+use super::{Recorder, WriteConfig, durability::Syncer, preallocate};
+
+#[derive(Clone, Debug)]
+pub struct UringConfig {
+    /// Writes kept in flight at once (also the ring size).
+    pub queue_depth: u32,
+    /// Register the block buffers up front and use WRITE_FIXED.
+    pub register_buffers: bool,
+    /// Register the file descriptor and refer to it by index.
+    pub fixed_files: bool,
+}
+
+impl Default for UringConfig {
+    fn default() -> Self {
+        Self {
+            queue_depth: 32,
+            register_buffers: false,
+            fixed_files: false,
+        }
+    }
+}
+
+// One in-flight write. A short completion is resubmitted for the rest.
+struct Slot {
+    offset: u64,
+    len: usize,
+    done: usize,
+    start: Instant,
+}
+
+// io_uring: keep up to queue_depth writes queued and hand them to the kernel
+// with one io_uring_enter per batch instead of one write(2) each.
+// Latency is measured per block, from queueing to its completion.
+pub fn run(cfg: &WriteConfig, rec: &mut Recorder) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .custom_flags(cfg.durability.open_flags())
+        .open(&cfg.path)?;
+    preallocate(cfg, &file, rec)?;
+    let fd = file.as_raw_fd();
+    let mut syncer = Syncer::new(cfg.durability);
+
+    let depth = cfg.uring.queue_depth.max(1);
+    // One buffer per slot so registered buffers can be addressed by index.
+    let bufs: Vec<Vec<u8>> = (0..depth).map(|_| vec![b'A'; cfg.block_size]).collect();
+
+    let mut ring = IoUring::new(depth)?;
+    rec.syscalls += 1;
+
+    if cfg.uring.register_buffers {
+        let iovecs: Vec<libc::iovec> = bufs
+            .iter()
+            .map(|b| libc::iovec {
+                iov_base: b.as_ptr() as *mut _,
+                iov_len: b.len(),
+            })
+            .collect();
+        // bufs outlives the ring (declared first, dropped last)
+        unsafe { ring.submitter().register_buffers(&iovecs)? };
+        rec.syscalls += 1;
+    }
+    if cfg.uring.fixed_files {
+        ring.submitter().register_files(&[fd])?;
+        rec.syscalls += 1;
+    }
+
+    let mut slots: Vec<Option<Slot>> = (0..depth).map(|_| None).collect();
+    let mut free: Vec<usize> = (0..depth as usize).rev().collect();
+    let mut next_offset = 0;
+    let mut in_flight = 0;
+    // Completions come back in any order, but the syncer's ranges assume one
+    // sequential run: blocks are only handed to it once everything below
+    // them has completed too.
+    let mut completed: BTreeMap<u64, u64> = BTreeMap::new();
+    let mut contiguous = 0;
+
+    loop {
+        while next_offset < cfg.total_size {
+            let Some(idx) = free.pop() else { break };
+            let len = (cfg.total_size - next_offset).min(cfg.block_size as u64) as usize;
+            let slot = Slot {
+                offset: next_offset,
+                len,
+                done: 0,
+                start: Instant::now(),
+            };
+            push(&mut ring, &prep(cfg, fd, &bufs[idx], idx, &slot))?;
+            slots[idx] = Some(slot);
+            next_offset += len as u64;
+            in_flight += 1;
+        }
+
+        if in_flight == 0 {
+            break;
+        }
+
+        ring.submit_and_wait(1)?;
+        rec.syscalls += 1;
+
+        let done: Vec<(u64, i32)> = ring
+            .completion()
+            .map(|cqe| (cqe.user_data(), cqe.result()))
+            .collect();
+
+        for (user_data, res) in done {
+            let idx = user_data as usize;
+            let slot = slots[idx].as_mut().expect("completion for an idle slot");
+
+            if res < 0 {
+                let err = io::Error::from_raw_os_error(-res);
+                if err.kind() == io::ErrorKind::Interrupted || res == -libc::EAGAIN {
+                    push(&mut ring, &prep(cfg, fd, &bufs[idx], idx, slot))?;
+                    continue;
+                }
+                return Err(err);
+            }
+            if res == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+
+            slot.done += res as usize;
+            if slot.done < slot.len {
+                push(&mut ring, &prep(cfg, fd, &bufs[idx], idx, slot))?;
+                continue;
+            }
+
+            let len = slot.len as u64;
+            rec.latency.record_duration(slot.start.elapsed());
+            rec.bytes += len;
+            completed.insert(slot.offset, len);
+            slots[idx] = None;
+            free.push(idx);
+            in_flight -= 1;
+
+            while let Some(len) = completed.remove(&contiguous) {
+                contiguous += len;
+                if syncer.wrote(len) {
+                    syncer.sync(&file, rec)?;
+                }
+            }
+        }
+    }
+
+    syncer.finish(&file, rec)
+}
+
+fn prep(cfg: &WriteConfig, fd: i32, buf: &[u8], idx: usize, slot: &Slot) -> squeue::Entry {
+    let ptr = buf[slot.done..].as_ptr();
+    let len = (slot.len - slot.done) as u32;
+    let offset = slot.offset + slot.done as u64;
+
+    let entry = match (cfg.uring.register_buffers, cfg.uring.fixed_files) {
+        (true, true) => opcode::WriteFixed::new(types::Fixed(0), ptr, len, idx as u16)
+            .offset(offset)
+            .build(),
+        (true, false) => opcode::WriteFixed::new(types::Fd(fd), ptr, len, idx as u16)
+            .offset(offset)
+            .build(),
+        (false, true) => opcode::Write::new(types::Fixed(0), ptr, len)
+            .offset(offset)
+            .build(),
+        (false, false) => opcode::Write::new(types::Fd(fd), ptr, len)
+            .offset(offset)
+            .build(),
+    };
+    entry.user_data(idx as u64)
+}
+
+fn push(ring: &mut IoUring, entry: &squeue::Entry) -> io::Result<()> {
+    // Never more entries than slots, and the ring has one per slot.
+    unsafe { ring.submission().push(entry) }
+        .map_err(|_| io::Error::other("io_uring submission queue full"))
+}
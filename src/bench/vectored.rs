@@ -0,0 +1,141 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, IoSlice, Write},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+};
+
+use super::{Recorder, WriteConfig, durability::Syncer, preallocate};
+
+// The kernel refuses more iovecs than this in one call.
+const IOV_MAX: usize = 1024;
+
+#[derive(Clone, Debug)]
+pub struct VectoredConfig {
+    /// Records (of block_size bytes each) gathered into one call.
+    pub batch: usize,
+    /// RWF_* flags. Zero means plain writev(2), anything else pwritev2(2).
+    pub flags: i32,
+}
+
+impl Default for VectoredConfig {
+    fn default() -> Self {
+        Self {
+            batch: 64,
+            flags: 0,
+        }
+    }
+}
+
+/// Parse a comma separated list like `dsync,hipri` into RWF_* flags.
+pub fn parse_rwf_flags(s: &str) -> Result<i32, String> {
+    s.split(',').try_fold(0, |flags, name| {
+        let flag = match name {
+            "hipri" => libc::RWF_HIPRI,
+            "dsync" => libc::RWF_DSYNC,
+            "sync" => libc::RWF_SYNC,
+            "nowait" => libc::RWF_NOWAIT,
+            "append" => libc::RWF_APPEND,
+            _ => {
+                return Err(format!(
+                    "unknown RWF flag '{}' (expected hipri, dsync, sync, nowait or append)",
+                    name
+                ));
+            }
+        };
+        Ok(flags | flag)
+    })
+}
+
+// Scatter-gather: the application still produces block_size records, but
+// `batch` of them reach the kernel in one writev instead of one write each.
+// Sits between unbuffered (a syscall per record) and BufWriter (a copy per record).
+pub fn run(cfg: &WriteConfig, rec: &mut Recorder) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .custom_flags(cfg.durability.open_flags())
+        .open(&cfg.path)?;
+    preallocate(cfg, &file, rec)?;
+    let mut syncer = Syncer::new(cfg.durability);
+
+    let batch = cfg.vectored.batch.clamp(1, IOV_MAX);
+    let record = vec![b'A'; cfg.block_size];
+
+    let blocks = cfg.blocks();
+    let mut offset = 0;
+    let mut i = 0;
+    while i < blocks {
+        let end = (i + batch as u64).min(blocks);
+        let mut slices: Vec<IoSlice> = (i..end)
+            .map(|b| IoSlice::new(&record[..cfg.block_len(b)]))
+            .collect();
+        let len: u64 = slices.iter().map(|s| s.len() as u64).sum();
+
+        let mut calls = 0;
+        rec.op(|| {
+            write_batch(
+                &mut file,
+                &mut slices,
+                offset,
+                cfg.vectored.flags,
+                &mut calls,
+            )
+        })?;
+        rec.syscalls += calls;
+        offset += len;
+        rec.bytes += len;
+        i = end;
+
+        if syncer.wrote(len) {
+            syncer.sync(&file, rec)?;
+        }
+    }
+
+    syncer.finish(&file, rec)
+}
+
+// Write every slice, picking up after partial writes.
+fn write_batch(
+    file: &mut File,
+    slices: &mut [IoSlice<'_>],
+    mut offset: u64,
+    flags: i32,
+    syscalls: &mut u64,
+) -> io::Result<()> {
+    let mut bufs = slices;
+    while !bufs.is_empty() {
+        *syscalls += 1;
+        let result = if flags == 0 {
+            file.write_vectored(bufs)
+        } else {
+            pwritev2(file, bufs, offset, flags)
+        };
+        let n = match result {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        offset += n as u64;
+        IoSlice::advance_slices(&mut bufs, n);
+    }
+    Ok(())
+}
+
+fn pwritev2(file: &File, bufs: &[IoSlice<'_>], offset: u64, flags: i32) -> io::Result<usize> {
+    // IoSlice is guaranteed to be ABI compatible with struct iovec on Unix.
+    let n = unsafe {
+        libc::pwritev2(
+            file.as_raw_fd(),
+            bufs.as_ptr() as *const libc::iovec,
+            bufs.len() as i32,
+            offset as i64,
+            flags,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
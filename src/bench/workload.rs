@@ -0,0 +1,323 @@
+// Seek-heavy workloads: pread/pwrite of fixed-size blocks at random or
+// strided offsets inside a preallocated file, mixing reads and writes.
+//
+// Offsets and the read/write choice come from a seeded RNG, so the same seed
+// replays exactly the same sequence of operations.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io,
+    os::unix::fs::FileExt,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    aligned_buf::{AlignedBuf, DEFAULT_ALIGNMENT},
+    cli::parse_size,
+    direct::DirectFile,
+    histogram::{Histogram, fmt_nanos},
+    report::Record,
+};
+
+/// Where the next block goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Sequential,
+    /// Uniformly random block-aligned offsets.
+    Random,
+    /// Jump this many bytes ahead each time, wrapping at the end of the file.
+    Strided(u64),
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Sequential => f.write_str("sequential"),
+            Pattern::Random => f.write_str("random"),
+            Pattern::Strided(stride) => write!(f, "stride={}", stride),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            None if s == "sequential" => Ok(Pattern::Sequential),
+            None if s == "random" => Ok(Pattern::Random),
+            Some(("stride", n)) => match parse_size(n)? {
+                0 => Err("stride must be non-zero".into()),
+                n => Ok(Pattern::Strided(n)),
+            },
+            _ => Err(format!(
+                "unknown pattern '{}' (expected sequential, random or stride=BYTES)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WorkloadConfig {
+    pub path: PathBuf,
+    /// Size of the file the offsets fall into; created up front if needed.
+    pub file_size: u64,
+    pub block_size: usize,
+    /// Number of pread/pwrite operations.
+    pub ops: u64,
+    /// Percentage of operations that are reads, 0..=100.
+    pub read_percent: u8,
+    pub pattern: Pattern,
+    pub seed: u64,
+    /// Open with O_DIRECT; offsets and block size must suit the device.
+    pub direct: bool,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("workload.dat"),
+            file_size: 64 << 20,
+            block_size: 4096,
+            ops: 10_000,
+            read_percent: 100,
+            pattern: Pattern::Random,
+            seed: 0,
+            direct: false,
+        }
+    }
+}
+
+impl WorkloadConfig {
+    fn slots(&self) -> u64 {
+        self.file_size / self.block_size as u64
+    }
+}
+
+pub struct WorkloadReport {
+    pub reads: u64,
+    pub writes: u64,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub read_latency: Histogram,
+    pub write_latency: Histogram,
+}
+
+impl WorkloadReport {
+    pub fn iops(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            (self.reads + self.writes) as f64 / secs
+        }
+    }
+
+    pub fn throughput_mib(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.bytes as f64 / (1024.0 * 1024.0) / secs
+        }
+    }
+
+    pub fn record(&self, cfg: &WorkloadConfig) -> Record {
+        Record::new("random_io", &cfg.path)
+            .param("pattern", cfg.pattern.to_string())
+            .param("read_percent", cfg.read_percent)
+            .param("ops", cfg.ops)
+            .param("block_size", cfg.block_size)
+            .param("file_size", cfg.file_size)
+            .param("seed", cfg.seed)
+            .param("direct", cfg.direct)
+            .result("reads", self.reads)
+            .result("writes", self.writes)
+            .result("bytes", self.bytes)
+            .elapsed(self.elapsed)
+            .result("iops", self.iops())
+            .result("throughput_mib_s", self.throughput_mib())
+            .latency("read_latency", &self.read_latency)
+            .latency("write_latency", &self.write_latency)
+    }
+}
+
+// Yields block-aligned offsets for the configured pattern.
+struct Offsets {
+    random: bool,
+    block_size: u64,
+    limit: u64,
+    step: u64,
+    sweep_start: u64,
+    next: u64,
+}
+
+impl Offsets {
+    fn new(cfg: &WorkloadConfig) -> Self {
+        let block_size = cfg.block_size as u64;
+        let limit = cfg.slots() * block_size;
+        let step = match cfg.pattern {
+            Pattern::Strided(stride) => stride.next_multiple_of(block_size).min(limit),
+            _ => block_size,
+        };
+        Self {
+            random: cfg.pattern == Pattern::Random,
+            block_size,
+            limit,
+            step,
+            sweep_start: 0,
+            next: 0,
+        }
+    }
+
+    fn next(&mut self, rng: &mut StdRng) -> u64 {
+        if self.random {
+            return rng.gen_range(0..self.limit / self.block_size) * self.block_size;
+        }
+        let offset = self.next;
+        self.next += self.step;
+        if self.next >= self.limit {
+            // each sweep starts a block further along, so a stride
+            // eventually touches every block
+            self.sweep_start = (self.sweep_start + self.block_size) % self.step;
+            self.next = self.sweep_start;
+        }
+        offset
+    }
+}
+
+/// Make sure `cfg.path` is at least `cfg.file_size` bytes of real data, so
+/// reads hit allocated blocks rather than holes. An existing large enough
+/// file is reused as is.
+pub fn prepare(cfg: &WorkloadConfig) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&cfg.path)?;
+    let mut len = file.metadata()?.len();
+    if len >= cfg.file_size {
+        return Ok(());
+    }
+
+    let chunk = vec![b'W'; 1 << 20];
+    while len < cfg.file_size {
+        let n = (cfg.file_size - len).min(chunk.len() as u64) as usize;
+        file.write_all_at(&chunk[..n], len)?;
+        len += n as u64;
+    }
+    file.sync_all()
+}
+
+// The two ways of issuing the I/O.
+enum Target {
+    Buffered(File),
+    Direct(DirectFile),
+}
+
+impl Target {
+    fn read(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        match self {
+            Target::Buffered(f) => f.read_exact_at(buf, offset),
+            Target::Direct(f) => match f.read_at(buf, offset)? {
+                n if n == buf.len() => Ok(()),
+                _ => Err(io::ErrorKind::UnexpectedEof.into()),
+            },
+        }
+    }
+
+    fn write(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        match self {
+            Target::Buffered(f) => f.write_all_at(buf, offset),
+            Target::Direct(f) => Ok(f.write_at(buf, offset)?),
+        }
+    }
+}
+
+pub fn run(cfg: &WorkloadConfig) -> io::Result<WorkloadReport> {
+    if cfg.block_size == 0 || cfg.slots() == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "file size must hold at least one non-empty block",
+        ));
+    }
+    prepare(cfg)?;
+
+    let (target, mut buf) = if cfg.direct {
+        let file = DirectFile::open_rw(&cfg.path)?;
+        let buf = file.buffer(cfg.block_size)?;
+        (Target::Direct(file), buf)
+    } else {
+        let file = OpenOptions::new().read(true).write(true).open(&cfg.path)?;
+        let buf = AlignedBuf::new(cfg.block_size, DEFAULT_ALIGNMENT)?;
+        (Target::Buffered(file), buf)
+    };
+    buf.fill(b'w');
+
+    let mut rng = StdRng::seed_from_u64(cfg.seed);
+    let mut offsets = Offsets::new(cfg);
+    let read_ratio = f64::from(cfg.read_percent.min(100)) / 100.0;
+
+    let mut report = WorkloadReport {
+        reads: 0,
+        writes: 0,
+        bytes: 0,
+        elapsed: Duration::ZERO,
+        read_latency: Histogram::new(),
+        write_latency: Histogram::new(),
+    };
+    let start = Instant::now();
+    for _ in 0..cfg.ops {
+        let offset = offsets.next(&mut rng);
+        let is_read = rng.gen_bool(read_ratio);
+        let op_start = Instant::now();
+        if is_read {
+            target.read(&mut buf, offset)?;
+            report.read_latency.record_duration(op_start.elapsed());
+            report.reads += 1;
+        } else {
+            target.write(&buf, offset)?;
+            report.write_latency.record_duration(op_start.elapsed());
+            report.writes += 1;
+        }
+        report.bytes += cfg.block_size as u64;
+    }
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
+
+pub fn print_report(r: &WorkloadReport) {
+    println!(
+        "{} ops in {}: {:.0} IOPS, {:.1} MiB/s",
+        r.reads + r.writes,
+        fmt_nanos(r.elapsed.as_nanos() as u64),
+        r.iops(),
+        r.throughput_mib()
+    );
+    println!(
+        "\n{:<6} {:>10} {:>9} {:>9} {:>9} {:>9} {:>9}",
+        "op", "count", "mean", "p50", "p90", "p99", "max"
+    );
+    for (name, h) in [("read", &r.read_latency), ("write", &r.write_latency)] {
+        if h.count() == 0 {
+            continue;
+        }
+        println!(
+            "{:<6} {:>10} {:>9} {:>9} {:>9} {:>9} {:>9}",
+            name,
+            h.count(),
+            fmt_nanos(h.mean() as u64),
+            fmt_nanos(h.percentile(50.0)),
+            fmt_nanos(h.percentile(90.0)),
+            fmt_nanos(h.percentile(99.0)),
+            fmt_nanos(h.max()),
+        );
+    }
+}
@@ -1,16 +1,75 @@
-use std::{fs::File, io::{BufWriter, Write}};
+use std::{
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    process,
+    time::Instant,
+};
+
+use linux::{
+    cli::Args,
+    histogram::fmt_nanos,
+    instrument,
+    report::{self, Record, Reporter},
+    runner::{self, RunConfig},
+};
 
 fn main() -> std::io::Result<()> {
-    let file = File::create("buffered.txt")?;
-    let mut writer = BufWriter::new(file);
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            let runs = RunConfig::from_args(&mut args)?;
+            if !args.finish()?.is_empty() {
+                return Err("unexpected positional arguments".to_string());
+            }
+            Ok((reporter, runs))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            eprintln!("Usage: {} {} {}", program, report::USAGE, runner::USAGE);
+            process::exit(1);
+        });
 
+    // compare the write(2) count in the summary with unbuffered_write
+    let _summary = instrument::enable();
     let chunk = vec![b'A'; 8192];
 
-    for _ in 0..1000 {
-        writer.write_all(&chunk)?;
-    }
+    let records = runs.run(|| -> std::io::Result<_> {
+        // each run's record (and the summary, for the last run) only counts its own calls
+        instrument::reset();
+        let start = Instant::now();
+
+        let file = instrument::open(
+            OpenOptions::new().write(true).create(true).truncate(true),
+            "buffered.txt",
+        )?;
+        let mut writer = BufWriter::new(file);
+
+        for _ in 0..1000 {
+            writer.write_all(&chunk)?;
+        }
 
-    writer.flush()?;
+        writer.flush()?;
+        drop(writer);
+        let elapsed = start.elapsed();
+
+        let record = instrument::add_to(
+            Record::new("buffered_write", "buffered.txt")
+                .param("chunk", chunk.len())
+                .param("chunks", 1000)
+                .elapsed(elapsed),
+        );
+        Ok((elapsed, record))
+    })?;
+
+    if runs.repeat > 1 && reporter.wants_text() {
+        let times: Vec<f64> = records.iter().map(|(t, _)| t.as_nanos() as f64).collect();
+        runner::print_summary("time", &[("buffered".into(), times)], |v| {
+            fmt_nanos(v as u64)
+        });
+    }
+    for (i, (_, record)) in records.into_iter().enumerate() {
+        reporter.emit(record.run(i))?;
+    }
     Ok(())
-    
-}
\ No newline at end of file
+}
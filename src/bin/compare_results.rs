@@ -0,0 +1,149 @@
+use std::{collections::BTreeMap, process};
+
+use linux::{
+    cli::Args,
+    compare::{self, Comparison},
+    histogram::fmt_nanos,
+    report,
+};
+
+// Exit status like diff(1): 0 no regression, 1 regressions, 2 trouble.
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} <before> <after> [--threshold PERCENT] [--metric NAME[,NAME...]] [--all]",
+        program
+    );
+    eprintln!("Compares two files written with --format json or csv and exits 1 if a metric");
+    eprintln!("got significantly worse by more than the threshold (default 5%)");
+    eprintln!("--all also lists metrics that didn't change");
+    process::exit(2);
+}
+
+struct Options {
+    before: String,
+    after: String,
+    threshold: f64,
+    metrics: Vec<String>,
+    all: bool,
+}
+
+fn parse_args(mut args: Args) -> Result<Options, String> {
+    let threshold = args.parse("--threshold")?.unwrap_or(5.0);
+    if threshold < 0.0 {
+        return Err("--threshold can't be negative".into());
+    }
+    let metrics = args
+        .value("--metric")?
+        .map(|list| list.split(',').map(String::from).collect())
+        .unwrap_or_default();
+    let all = args.flag("--all");
+    match args.finish()?.as_slice() {
+        [before, after] => Ok(Options {
+            before: before.clone(),
+            after: after.clone(),
+            threshold,
+            metrics,
+            all,
+        }),
+        _ => Err("expected two result files".into()),
+    }
+}
+
+// Benchmark name plus the parameters that tell its configurations apart;
+// the ones every configuration shares would only be noise on each line.
+fn labels(comparisons: &[Comparison]) -> Vec<String> {
+    let mut values: BTreeMap<(&str, &str), Vec<&str>> = BTreeMap::new();
+    for c in comparisons {
+        for (k, v) in &c.params {
+            let seen = values.entry((&c.benchmark, k)).or_default();
+            if !seen.contains(&v.as_str()) {
+                seen.push(v);
+            }
+        }
+    }
+    comparisons
+        .iter()
+        .map(|c| {
+            let mut label = c.benchmark.clone();
+            for (k, v) in &c.params {
+                if values[&(c.benchmark.as_str(), k.as_str())].len() > 1 {
+                    label.push_str(&format!(" {}={}", k, v));
+                }
+            }
+            label
+        })
+        .collect()
+}
+
+fn fmt_value(metric: &str, v: f64) -> String {
+    if metric.ends_with("_ns") {
+        fmt_nanos(v.max(0.0) as u64)
+    } else {
+        format!("{:.1}", v)
+    }
+}
+
+fn main() {
+    let args = Args::from_env();
+    let program = args.program().to_string();
+    let opts = parse_args(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        usage(&program)
+    });
+
+    let load = |path: &str| {
+        report::read_results(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(2);
+        })
+    };
+    let (before, after) = (load(&opts.before), load(&opts.after));
+    let comparisons = compare::compare(&before, &after, &opts.metrics);
+    if comparisons.is_empty() {
+        eprintln!("no configuration and metric appears in both files");
+        process::exit(2);
+    }
+
+    println!(
+        "{:<40} {:<24} {:>10} {:>10} {:>9} {:>6}  verdict",
+        "configuration", "metric", "before", "after", "change", "runs"
+    );
+    let mut regressions = 0;
+    for (c, label) in comparisons.iter().zip(labels(&comparisons)) {
+        let verdict = if c.is_regression(opts.threshold) {
+            regressions += 1;
+            "REGRESSION"
+        } else if c.is_improvement(opts.threshold) {
+            "improved"
+        } else if c.significant == Some(true) {
+            "within threshold"
+        } else if c.significant.is_none() {
+            "too few runs"
+        } else {
+            "noise"
+        };
+        if !opts.all && matches!(verdict, "noise" | "within threshold" | "too few runs") {
+            continue;
+        }
+        println!(
+            "{:<40} {:<24} {:>10} {:>10} {:>+8.1}% {:>6}  {}",
+            label,
+            c.metric,
+            fmt_value(&c.metric, c.before.mean),
+            fmt_value(&c.metric, c.after.mean),
+            c.change_percent(),
+            format!("{}/{}", c.before.n, c.after.n),
+            verdict
+        );
+    }
+
+    println!(
+        "\n{} metrics compared, {} regressed by more than {}%",
+        comparisons.len(),
+        regressions,
+        opts.threshold
+    );
+    if regressions > 0 {
+        process::exit(1);
+    }
+}
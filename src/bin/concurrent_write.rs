@@ -0,0 +1,124 @@
+use std::process;
+
+use linux::{
+    bench::concurrent::{self, ConcurrentConfig, ConcurrentReport, Method, Spawn},
+    cli::Args,
+    histogram::fmt_nanos,
+    report::{self, Reporter},
+    runner::{self, RunConfig},
+};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [--method all|append|pwrite|flock|fcntl|unlocked] [--workers N] [--processes] [--records N] [--record-size BYTES] [--path FILE]",
+        program
+    );
+    eprintln!("       {} {}", report::USAGE, runner::USAGE);
+    eprintln!("N threads (or forked processes with --processes) write records to one file,");
+    eprintln!("which is then checked for torn, lost or duplicated records");
+    eprintln!("fcntl needs --processes; unlocked is expected to fail the check");
+    process::exit(1);
+}
+
+fn parse_args(mut args: Args) -> Result<(Vec<Method>, ConcurrentConfig), String> {
+    let mut cfg = ConcurrentConfig::default();
+    if args.flag("--processes") {
+        cfg.spawn = Spawn::Processes;
+    }
+    let methods = match args.value("--method")?.as_deref() {
+        // record locks don't work between threads, so "all" leaves them out
+        None | Some("all") => Method::ALL
+            .into_iter()
+            .filter(|&m| m != Method::Fcntl || cfg.spawn == Spawn::Processes)
+            .collect(),
+        Some(list) => list
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    if let Some(workers) = args.parse("--workers")? {
+        cfg.workers = workers;
+    }
+    if let Some(records) = args.parse("--records")? {
+        cfg.records = records;
+    }
+    if let Some(size) = args.size("--record-size")? {
+        cfg.record_size = size as usize;
+    }
+    if let Some(path) = args.value("--path")? {
+        cfg.path = path.into();
+    }
+    if !args.finish()?.is_empty() {
+        return Err("unexpected positional arguments".into());
+    }
+    Ok((methods, cfg))
+}
+
+fn main() {
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs, (methods, cfg)) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            Ok((
+                reporter,
+                RunConfig::from_args(&mut args)?,
+                parse_args(args)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            usage(&program)
+        });
+
+    if reporter.wants_text() {
+        println!(
+            "{} {} x {} records of {} bytes into {}\n",
+            cfg.workers,
+            cfg.spawn,
+            cfg.records,
+            cfg.record_size,
+            cfg.path.display()
+        );
+    }
+
+    let mut reports = Vec::new();
+    let mut failed = false;
+    for &method in &methods {
+        match runs.run(|| concurrent::run(&cfg, method)) {
+            Ok(r) => reports.extend(r),
+            Err(e) => {
+                eprintln!("{}: {}", method, e);
+                failed = true;
+            }
+        }
+    }
+    // the unlocked method is there to be caught; anything else is a bug
+    let corrupt = reports
+        .iter()
+        .filter(|r| r.method.is_safe() && !r.verification.is_clean())
+        .count();
+
+    if reporter.wants_text() {
+        concurrent::print_table(&reports);
+        if runs.repeat > 1 {
+            let label = |r: &ConcurrentReport| r.method.to_string();
+            let time = |r: &ConcurrentReport| r.elapsed.as_nanos() as f64;
+            runner::summarise("time", &reports, runs.repeat, label, time, |v| {
+                fmt_nanos(v as u64)
+            });
+        }
+    }
+    for (i, report) in reports.iter().enumerate() {
+        if let Err(e) = reporter.emit(report.record(&cfg).run(i % runs.repeat as usize)) {
+            eprintln!("failed to write results: {}", e);
+            process::exit(1);
+        }
+    }
+    if corrupt > 0 {
+        eprintln!("{} run(s) of a safe method left a corrupt file", corrupt);
+        failed = true;
+    }
+    if failed {
+        process::exit(1);
+    }
+}
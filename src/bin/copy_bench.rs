@@ -0,0 +1,157 @@
+use std::{path::PathBuf, process};
+
+use linux::{
+    cli::Args,
+    copy::{self, CopyReport, Method},
+    histogram::fmt_nanos,
+    report::{self, Reporter},
+    runner::{self, RunConfig},
+};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} <source> [destination] [--method all|read-write|sendfile|splice|copy-file-range] [--chunk BYTES]",
+        program
+    );
+    eprintln!("       {} {}", report::USAGE, runner::USAGE);
+    eprintln!("Copies source with each method, checks the copy byte for byte and compares timings");
+    eprintln!("destination defaults to <source>.copy");
+    process::exit(1);
+}
+
+struct Options {
+    src: PathBuf,
+    dst: PathBuf,
+    methods: Vec<Method>,
+    chunk: usize,
+}
+
+fn parse_args(mut args: Args) -> Result<Options, String> {
+    let methods = match args.value("--method")?.as_deref() {
+        None | Some("all") => Method::ALL.to_vec(),
+        Some(list) => list
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    let chunk = args.size("--chunk")?.unwrap_or(1 << 20) as usize;
+    let (src, dst) = match args.finish()?.as_slice() {
+        [src] => (PathBuf::from(src), PathBuf::from(format!("{}.copy", src))),
+        [src, dst] => (PathBuf::from(src), PathBuf::from(dst)),
+        _ => return Err("expected a source and optionally a destination".into()),
+    };
+    Ok(Options {
+        src,
+        dst,
+        methods,
+        chunk,
+    })
+}
+
+fn print_table(reports: &[CopyReport]) {
+    println!(
+        "{:<16} {:>12} {:>10} {:>10} {:>10} {:>8}",
+        "method", "bytes", "time", "MiB/s", "syscalls", "reflink"
+    );
+    for r in reports {
+        println!(
+            "{:<16} {:>12} {:>10} {:>10.1} {:>10} {:>8}",
+            r.method.name(),
+            r.bytes,
+            fmt_nanos(r.elapsed.as_nanos() as u64),
+            r.throughput_mib(),
+            r.syscalls,
+            match r.shared {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "?",
+            }
+        );
+    }
+}
+
+fn main() {
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs, opts) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            Ok((
+                reporter,
+                RunConfig::from_args(&mut args)?,
+                parse_args(args)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            usage(&program)
+        });
+
+    if reporter.wants_text() {
+        println!(
+            "copying {} to {} in chunks of {} bytes\n",
+            opts.src.display(),
+            opts.dst.display(),
+            opts.chunk
+        );
+    }
+
+    let mut reports = Vec::new();
+    let mut failed = false;
+    for &method in &opts.methods {
+        let copies = match runs.run(|| copy::copy(method, &opts.src, &opts.dst, opts.chunk)) {
+            Ok(copies) => copies,
+            Err(e) => {
+                eprintln!("{}: {}", method, e);
+                failed = true;
+                continue;
+            }
+        };
+        // every run overwrites the same destination; checking the last one
+        // checks the method
+        match copy::verify(&opts.src, &opts.dst) {
+            Ok(None) => reports.extend(copies),
+            Ok(Some(offset)) => {
+                eprintln!(
+                    "{}: copy differs from the source at offset {}",
+                    method, offset
+                );
+                failed = true;
+            }
+            Err(e) => {
+                eprintln!("{}: verify: {}", method, e);
+                failed = true;
+            }
+        }
+    }
+
+    if reporter.wants_text() {
+        print_table(&reports);
+        if runs.repeat > 1 {
+            let label = |r: &CopyReport| r.method.to_string();
+            let time = |r: &CopyReport| r.elapsed.as_nanos() as f64;
+            runner::summarise("time", &reports, runs.repeat, label, time, |v| {
+                fmt_nanos(v as u64)
+            });
+            runner::summarise(
+                "MiB/s",
+                &reports,
+                runs.repeat,
+                label,
+                |r| r.throughput_mib(),
+                |v| format!("{:.1}", v),
+            );
+        }
+    }
+    for (i, report) in reports.iter().enumerate() {
+        let record = report
+            .record(&opts.dst, opts.chunk)
+            .run(i % runs.repeat as usize);
+        if let Err(e) = reporter.emit(record) {
+            eprintln!("failed to write results: {}", e);
+            process::exit(1);
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,27 @@
+use std::{env, process};
+
+use linux::probe;
+
+// Print what O_DIRECT would need (and whether it works) for each path given.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <path>...", args[0]);
+        process::exit(1);
+    }
+
+    let mut failed = false;
+    for path in &args[1..] {
+        match probe::probe(path) {
+            Ok(p) => println!("{}\n", p),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
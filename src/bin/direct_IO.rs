@@ -1,48 +1,170 @@
-use std::{ffi::CString, ptr};
+use std::{
+    fs::{self, File},
+    io::Write,
+    process,
+    time::{Duration, Instant},
+};
+
+use linux::{
+    cli::Args,
+    direct::DirectFile,
+    histogram::fmt_nanos,
+    instrument, probe,
+    report::{self, Record, Reporter},
+    runner::{self, RunConfig},
+};
+
+fn parse_args(args: Args) -> Result<(usize, String), String> {
+    let positional = args.finish()?;
+    if positional.len() > 2 {
+        return Err("expected at most a number of bytes and a file".into());
+    }
+    // Any length works; the tail block is padded and truncated away again
+    let size = match positional.first() {
+        Some(s) => s
+            .parse()
+            .map_err(|e| format!("invalid number of bytes '{}': {}", s, e))?,
+        None => 4096,
+    };
+    let path = positional
+        .get(1)
+        .cloned()
+        .unwrap_or_else(|| "direct_io.txt".to_string());
+    Ok((size, path))
+}
 
-use libc::{close, open, posix_memalign, O_CREAT, O_DIRECT, O_RDWR, S_IRUSR, S_IWUSR};
 fn main() {
-    // 4096-byte alignment
-    const ALIGNMENT: usize = 4096;
-    const SIZE: usize = 4096;
-
-    //Allocate the aligned buffer
-    let mut buf: *mut u8 = ptr::null_mut();
-    unsafe {
-        if posix_memalign(&mut buf as *mut *mut u8 as *mut _, ALIGNMENT, SIZE) != 0 {
-            panic!("posix_memalign failed");
-        }
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs, (size, path)) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            Ok((
+                reporter,
+                RunConfig::from_args(&mut args)?,
+                parse_args(args)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            eprintln!(
+                "Usage: {} [num_bytes] [file] {} {}",
+                program,
+                report::USAGE,
+                runner::USAGE
+            );
+            process::exit(1);
+        });
+    let verbose = reporter.wants_text();
+
+    let _summary = instrument::enable();
+
+    // fill with A..Z so a misplaced block would show up on read-back
+    let data: Vec<u8> = (0..size).map(|i| b'A' + (i % 26) as u8).collect();
+
+    // check what the filesystem wants before opening with O_DIRECT
+    let probe = probe::probe(&path).unwrap_or_else(|e| {
+        eprintln!("failed to probe {}: {}", path, e);
+        process::exit(1);
+    });
+    if verbose && let Some(why) = &probe.unsupported {
+        println!("O_DIRECT is not available for {}: {}", path, why);
+        println!("Falling back to a buffered write followed by fsync");
+    }
+    let results = runs
+        .run(|| {
+            // each run's record (and the summary, for the last run) only counts its own calls
+            instrument::reset();
+            let (elapsed, record) = match probe.unsupported {
+                Some(_) => buffered_fallback(&path, &data, verbose)?,
+                None => direct(&path, &data, probe.fs_type, verbose)?,
+            };
+            Ok::<_, String>((elapsed, instrument::add_to(record)))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
 
-        // fill the buffer with A
-        ptr::write_bytes(buf, b'A', SIZE);
+    if runs.repeat > 1 && verbose {
+        let times: Vec<f64> = results.iter().map(|(t, _)| t.as_nanos() as f64).collect();
+        let label = if probe.unsupported.is_some() {
+            "buffered"
+        } else {
+            "direct"
+        };
+        runner::print_summary("time", &[(label.into(), times)], |v| fmt_nanos(v as u64));
     }
+    for (i, (_, record)) in results.into_iter().enumerate() {
+        if let Err(e) = reporter.emit(record.run(i)) {
+            eprintln!("failed to write results: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
+fn direct(
+    path: &str,
+    data: &[u8],
+    fs_type: &str,
+    verbose: bool,
+) -> Result<(Duration, Record), String> {
+    let start = Instant::now();
     // open the fle with O_DIRECT
-    let path = CString::new("direct_io.txt").unwrap();
-    let fd = unsafe {
-        open(
-            path.as_ptr(),
-        O_CREAT | O_RDWR | O_DIRECT,
-               S_IRUSR | S_IWUSR,)
-    };
+    let file = DirectFile::create(path)
+        .map_err(|e| format!("failed to open {} with O_DIRECT: {}", path, e))?;
 
-    if fd < 0 {
-        panic!("failed to open with O_DIRECT");
+    // write to file directly (through an aligned buffer)
+    file.write_all(data)
+        .map_err(|e| format!("Failed to write all data: {}", e))?;
+    let elapsed = start.elapsed();
+    if verbose {
+        println!(
+            "Wrote {} bytes using Direct I/O ({}-byte alignment on {}, {} syscalls)",
+            data.len(),
+            file.alignment(),
+            fs_type,
+            file.syscalls()
+        );
     }
 
-    // write to file directly (using the aligned buffer)
-    let written = unsafe {
-        libc::write(fd, buf as *const _, SIZE)
-    };
-
-    if written != SIZE as isize {
-        eprintln!("Failed to write all data. wrote: {}", written);
+    // read it back, also with O_DIRECT, and compare
+    file.verify(data)
+        .map_err(|e| format!("Verification failed: {}", e))?;
+    if verbose {
+        println!("Read back and verified {} bytes", data.len());
     }
 
-    unsafe {
-        close(fd);
-        libc::free(buf as *mut _);
+    let record = Record::new("direct_IO", path)
+        .param("size", data.len())
+        .param("mode", "direct")
+        .param("alignment", file.alignment())
+        .elapsed(elapsed);
+    Ok((elapsed, record))
+}
+
+fn buffered_fallback(path: &str, data: &[u8], verbose: bool) -> Result<(Duration, Record), String> {
+    let start = Instant::now();
+    File::create(path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .map_err(|e| format!("Buffered write failed: {}", e))?;
+    let elapsed = start.elapsed();
+
+    match fs::read(path) {
+        Ok(back) if back == data => {
+            if verbose {
+                println!("Wrote and verified {} bytes (buffered + fsync)", data.len());
+            }
+        }
+        Ok(_) => return Err("Verification failed: read-back data differs".into()),
+        Err(e) => return Err(format!("Read-back failed: {}", e)),
     }
-    println!("Wrote {} bytes using Direct I/O", written)
-    
-}
\ No newline at end of file
+
+    let record = Record::new("direct_IO", path)
+        .param("size", data.len())
+        .param("mode", "buffered")
+        .elapsed(elapsed);
+    Ok((elapsed, record))
+}
@@ -0,0 +1,110 @@
+use std::{fs::OpenOptions, process};
+
+use linux::{
+    cli::{Args, parse_size},
+    sparse::{self, Falloc, Layout},
+};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} <file> [--allocate OFFSET:LEN] [--keep-size OFFSET:LEN] [--punch-hole OFFSET:LEN] [--zero-range OFFSET:LEN]",
+        program
+    );
+    eprintln!(
+        "Applies the fallocate operations given (in the order above), then prints the file's data/hole layout"
+    );
+    process::exit(1);
+}
+
+// "4K:1M" -> (4096, 1048576)
+fn parse_range(s: &str) -> Result<(u64, u64), String> {
+    let (offset, len) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected OFFSET:LEN, got '{}'", s))?;
+    Ok((parse_size(offset)?, parse_size(len)?))
+}
+
+struct Op {
+    op: Falloc,
+    offset: u64,
+    len: u64,
+}
+
+fn parse_args(mut args: Args) -> Result<(String, Vec<Op>), String> {
+    let mut ops = Vec::new();
+    for (flag, op) in [
+        ("--allocate", Falloc::Allocate),
+        ("--keep-size", Falloc::KeepSize),
+        ("--punch-hole", Falloc::PunchHole),
+        ("--zero-range", Falloc::ZeroRange),
+    ] {
+        if let Some(range) = args.value(flag)? {
+            let (offset, len) = parse_range(&range).map_err(|e| format!("{}: {}", flag, e))?;
+            ops.push(Op { op, offset, len });
+        }
+    }
+    match args.finish()?.as_slice() {
+        [path] => Ok((path.clone(), ops)),
+        _ => Err("expected exactly one file".into()),
+    }
+}
+
+fn print_layout(path: &str, layout: &Layout) {
+    println!("{}: {} bytes", path, layout.size);
+    println!(
+        "{:<6} {:>14} {:>14} {:>14}",
+        "kind", "offset", "end", "length"
+    );
+    for s in &layout.segments {
+        println!(
+            "{:<6} {:>14} {:>14} {:>14}",
+            if s.data { "data" } else { "hole" },
+            s.offset,
+            s.offset + s.len,
+            s.len
+        );
+    }
+    println!(
+        "\ndata {} bytes, holes {} bytes, allocated {} bytes on disk",
+        layout.data_bytes(),
+        layout.hole_bytes(),
+        layout.allocated
+    );
+    match layout.extents {
+        Some(n) => println!("{} physical extent(s)", n),
+        None => println!("physical extents unknown (no FIEMAP on this filesystem)"),
+    }
+}
+
+fn main() {
+    let args = Args::from_env();
+    let program = args.program().to_string();
+    let (path, ops) = parse_args(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        usage(&program)
+    });
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(!ops.is_empty())
+        .open(&path)
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+
+    for Op { op, offset, len } in ops {
+        if let Err(e) = sparse::fallocate(&file, op, offset, len) {
+            eprintln!("{}: fallocate {:?} {}:{}: {}", path, op, offset, len, e);
+            process::exit(1);
+        }
+    }
+
+    match sparse::layout(&file) {
+        Ok(layout) => print_layout(&path, &layout),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::{path::PathBuf, process};
+
+use linux::{cli::Args, lock};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [--dir DIR] [--case NAME[,NAME...]] [--list]",
+        program
+    );
+    eprintln!("Checks how flock, POSIX record locks and OFD locks behave across opens,");
+    eprintln!("closes, threads and forks; exits 1 if the kernel disagrees with a case");
+    process::exit(1);
+}
+
+struct Options {
+    dir: PathBuf,
+    names: Option<Vec<String>>,
+    list: bool,
+}
+
+fn parse_args(mut args: Args) -> Result<Options, String> {
+    let dir = args
+        .value("--dir")?
+        .map_or(PathBuf::from("."), PathBuf::from);
+    let names = args
+        .value("--case")?
+        .map(|list| list.split(',').map(String::from).collect());
+    let list = args.flag("--list");
+    if !args.finish()?.is_empty() {
+        return Err("unexpected positional arguments".into());
+    }
+    Ok(Options { dir, names, list })
+}
+
+fn main() {
+    let args = Args::from_env();
+    let program = args.program().to_string();
+    let Options { dir, names, list } = parse_args(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        usage(&program)
+    });
+
+    let mut cases = lock::cases();
+    if let Some(names) = &names {
+        if let Some(unknown) = names.iter().find(|n| !cases.iter().any(|c| c.name == *n)) {
+            eprintln!("unknown case '{}' (see --list)", unknown);
+            process::exit(1);
+        }
+        cases.retain(|c| names.iter().any(|n| n == c.name));
+    }
+    if list {
+        for case in &cases {
+            println!("{:<24} {}", case.name, case.claim);
+        }
+        return;
+    }
+
+    let path = lock::scratch_path(&dir);
+    let mut failed = 0;
+    println!("{:<24} {:<6} {:<34} claim", "case", "result", "observed");
+    for case in &cases {
+        let (result, observed) = match case.run(&path) {
+            Ok(observed) if observed == case.expected => ("PASS", observed.to_string()),
+            Ok(observed) => ("FAIL", format!("{} (expected {})", observed, case.expected)),
+            Err(e) => ("ERROR", e.to_string()),
+        };
+        if result != "PASS" {
+            failed += 1;
+        }
+        println!(
+            "{:<24} {:<6} {:<34} {}",
+            case.name, result, observed, case.claim
+        );
+    }
+
+    if failed > 0 {
+        eprintln!("\n{} of {} cases failed", failed, cases.len());
+        process::exit(1);
+    }
+}
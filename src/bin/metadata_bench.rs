@@ -0,0 +1,117 @@
+use std::process;
+
+use linux::{
+    bench::metadata::{self, Fixture, MetadataConfig, MetadataReport, Op},
+    cli::Args,
+    histogram::fmt_nanos,
+    report::{self, Reporter},
+    runner::{self, RunConfig},
+};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [--op all|stat|open|rename|rename-dir|link|symlink|readdir|utimens|chmod[,...]] [--files N] [--dir DIR]",
+        program
+    );
+    eprintln!(
+        "       [--scheme sequential|random|long|prefix|fanout=N] [--seed N] [--readdir-passes N]"
+    );
+    eprintln!("       {} {}", report::USAGE, runner::USAGE);
+    eprintln!("DIR is created for the run and removed afterwards; it must not exist");
+    process::exit(1);
+}
+
+fn parse_args(mut args: Args) -> Result<(Vec<Op>, MetadataConfig), String> {
+    let mut cfg = MetadataConfig::default();
+    let ops = match args.value("--op")?.as_deref() {
+        None | Some("all") => Op::ALL.to_vec(),
+        Some(list) => list
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    if let Some(files) = args.parse("--files")? {
+        cfg.files = files;
+    }
+    if let Some(dir) = args.value("--dir")? {
+        cfg.dir = dir.into();
+    }
+    if let Some(scheme) = args.parse("--scheme")? {
+        cfg.scheme = scheme;
+    }
+    if let Some(seed) = args.parse("--seed")? {
+        cfg.seed = seed;
+    }
+    if let Some(passes) = args.parse("--readdir-passes")? {
+        cfg.readdir_passes = passes;
+    }
+    if !args.finish()?.is_empty() {
+        return Err("unexpected positional arguments".into());
+    }
+    Ok((ops, cfg))
+}
+
+fn main() {
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs, (ops, cfg)) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            Ok((
+                reporter,
+                RunConfig::from_args(&mut args)?,
+                parse_args(args)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            usage(&program)
+        });
+
+    let fixture = Fixture::create(&cfg).unwrap_or_else(|e| {
+        eprintln!("{}: {}", cfg.dir.display(), e);
+        process::exit(1);
+    });
+    if reporter.wants_text() {
+        println!(
+            "{} files named {} in {}\n",
+            fixture.names().len(),
+            cfg.scheme,
+            cfg.dir.display()
+        );
+    }
+
+    let mut reports = Vec::new();
+    let mut failed = false;
+    for &op in &ops {
+        match runs.run(|| metadata::run(op, &cfg, &fixture)) {
+            Ok(r) => reports.extend(r),
+            Err(e) => {
+                // the directory may be half renamed now; later ops can't trust it
+                eprintln!("{}: {}", op, e);
+                failed = true;
+                break;
+            }
+        }
+    }
+    drop(fixture);
+
+    if reporter.wants_text() {
+        metadata::print_table(&reports);
+        if runs.repeat > 1 {
+            let label = |r: &MetadataReport| r.op.to_string();
+            let mean = |r: &MetadataReport| r.latency.mean();
+            runner::summarise("mean latency", &reports, runs.repeat, label, mean, |v| {
+                fmt_nanos(v as u64)
+            });
+        }
+    }
+    for (i, report) in reports.iter().enumerate() {
+        if let Err(e) = reporter.emit(report.record(&cfg).run(i % runs.repeat as usize)) {
+            eprintln!("failed to write results: {}", e);
+            process::exit(1);
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
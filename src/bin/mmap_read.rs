@@ -0,0 +1,170 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    process,
+    time::{Duration, Instant},
+};
+
+use linux::{
+    cli::Args,
+    histogram::fmt_nanos,
+    mmap::{self, Advice, Mapping},
+    report::{self, Record, Reporter},
+    runner::{self, RunConfig},
+};
+
+// Read a whole file twice, once with read(2) and once through a mapping, and
+// show what each costs: syscalls for the first, page faults for the second.
+struct Sample {
+    name: &'static str,
+    elapsed: Duration,
+    checksum: u64,
+    syscalls: u64,
+    minor_faults: u64,
+    major_faults: u64,
+}
+
+fn measure(name: &'static str, f: impl Fn() -> io::Result<(u64, u64)>) -> io::Result<Sample> {
+    let faults_before = mmap::page_faults();
+    let start = Instant::now();
+    let (checksum, syscalls) = f()?;
+    let elapsed = start.elapsed();
+    let faults = mmap::page_faults();
+    Ok(Sample {
+        name,
+        elapsed,
+        checksum,
+        syscalls,
+        minor_faults: faults.0 - faults_before.0,
+        major_faults: faults.1 - faults_before.1,
+    })
+}
+
+fn main() {
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs, (path, advice, buf_size)) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            Ok((
+                reporter,
+                RunConfig::from_args(&mut args)?,
+                parse_args(args)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            eprintln!(
+                "Usage: {} <file> [--madvise ADVICE] [--buf-size BYTES] {} {}",
+                program,
+                report::USAGE,
+                runner::USAGE
+            );
+            process::exit(1);
+        });
+
+    let mut samples = Vec::new();
+    for name in ["read", "mmap"] {
+        let result = runs.run(|| match name {
+            "read" => measure(name, || read_syscalls(&path, buf_size)),
+            _ => measure(name, || read_mapped(&path, advice)),
+        });
+        match result {
+            Ok(s) => samples.extend(s),
+            Err(e) => eprintln!("{}: {}", name, e),
+        }
+    }
+
+    if reporter.wants_text() {
+        println!(
+            "{:<6} {:>10} {:>10} {:>8} {:>8}",
+            "method", "time", "syscalls", "minflt", "majflt"
+        );
+        for s in &samples {
+            println!(
+                "{:<6} {:>10} {:>10} {:>8} {:>8}  checksum {:x}",
+                s.name,
+                fmt_nanos(s.elapsed.as_nanos() as u64),
+                s.syscalls,
+                s.minor_faults,
+                s.major_faults,
+                s.checksum
+            );
+        }
+        if runs.repeat > 1 {
+            let time = |s: &Sample| s.elapsed.as_nanos() as f64;
+            runner::summarise(
+                "time",
+                &samples,
+                runs.repeat,
+                |s| s.name.into(),
+                time,
+                |v| fmt_nanos(v as u64),
+            );
+        }
+    }
+
+    for (i, s) in samples.iter().enumerate() {
+        let record = Record::new("mmap_read", &path)
+            .run(i % runs.repeat as usize)
+            .param("method", s.name)
+            .param("buf_size", buf_size)
+            .param("madvise", advice.map(|a| a.to_string()))
+            .elapsed(s.elapsed)
+            .result("syscalls", s.syscalls)
+            .result("minor_faults", s.minor_faults)
+            .result("major_faults", s.major_faults)
+            .result("checksum", s.checksum);
+        if let Err(e) = reporter.emit(record) {
+            eprintln!("failed to write results: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_args(mut args: Args) -> Result<(String, Option<Advice>, usize), String> {
+    let advice = args.parse("--madvise")?;
+    let buf_size = args.size("--buf-size")?.unwrap_or(64 << 10) as usize;
+    match args.finish()?.as_slice() {
+        [path] => Ok((path.clone(), advice, buf_size.max(1))),
+        _ => Err("expected exactly one file".into()),
+    }
+}
+
+// Simple additive checksum so neither loop can be optimised away.
+fn checksum(sum: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(sum, |acc, &b| acc.wrapping_add(b as u64))
+}
+
+fn read_syscalls(path: &str, buf_size: usize) -> io::Result<(u64, u64)> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; buf_size];
+    let (mut sum, mut syscalls) = (0, 1);
+    loop {
+        syscalls += 1;
+        let n = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        sum = checksum(sum, &buf[..n]);
+    }
+    Ok((sum, syscalls))
+}
+
+fn read_mapped(path: &str, advice: Option<Advice>) -> io::Result<(u64, u64)> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+    if len == 0 {
+        return Ok((0, 1));
+    }
+    let map = Mapping::read_only(&file, len)?;
+    let mut syscalls = 2;
+    if let Some(advice) = advice {
+        map.advise(advice)?;
+        syscalls += 1;
+    }
+    let sum = checksum(0, &map);
+    // open + mmap (+ madvise) + munmap when map drops
+    Ok((sum, syscalls + 1))
+}
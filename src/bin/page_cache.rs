@@ -0,0 +1,109 @@
+use std::process;
+
+use linux::{
+    cache::{self, MemInfo},
+    cli::Args,
+};
+
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} <file>... [--evict] [--width COLUMNS]", program);
+    eprintln!("Shows how much of each file is in the page cache; --evict drops it first");
+    process::exit(1);
+}
+
+fn mib(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+fn print_meminfo(label: &str, m: &MemInfo) {
+    println!(
+        "{:<8} cached {:>10.1} MiB   dirty {:>8.1} MiB   writeback {:>8.1} MiB",
+        label,
+        mib(m.cached),
+        mib(m.dirty),
+        mib(m.writeback)
+    );
+}
+
+struct Options {
+    evict: bool,
+    width: usize,
+    paths: Vec<String>,
+}
+
+fn parse_args(mut args: Args) -> Result<Options, String> {
+    let evict = args.flag("--evict");
+    let width = args.parse("--width")?.unwrap_or(64);
+    let paths = args.finish()?;
+    if paths.is_empty() {
+        return Err("expected at least one file".into());
+    }
+    Ok(Options {
+        evict,
+        width,
+        paths,
+    })
+}
+
+fn main() {
+    let args = Args::from_env();
+    let program = args.program().to_string();
+    let Options {
+        evict,
+        width,
+        paths,
+    } = parse_args(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        usage(&program)
+    });
+
+    match cache::meminfo() {
+        Ok(m) => print_meminfo("system", &m),
+        Err(e) => eprintln!("/proc/meminfo: {}", e),
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        if evict {
+            match cache::evict(path) {
+                Ok(how) => println!("{}: evicted ({:?})", path, how),
+                Err(e) => {
+                    eprintln!("{}: evict: {}", path, e);
+                    failed = true;
+                    continue;
+                }
+            }
+        }
+        match cache::residency(path) {
+            Ok(r) => {
+                println!(
+                    "{}: {} of {} pages resident ({:.1}%, {:.1} of {:.1} MiB)",
+                    path,
+                    r.resident(),
+                    r.pages.len(),
+                    r.percent(),
+                    mib(r.resident_bytes()),
+                    mib(r.file_size)
+                );
+                if !r.pages.is_empty() {
+                    println!("  [{}]", r.map(width));
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                failed = true;
+            }
+        }
+    }
+
+    if evict {
+        match cache::meminfo() {
+            Ok(m) => print_meminfo("after", &m),
+            Err(e) => eprintln!("/proc/meminfo: {}", e),
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
@@ -1,67 +1,434 @@
-use std::{env, fs::{self, File}, io::{self, Write}, path::PathBuf, time::Instant};
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::{
+        Arc, Barrier,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use rand::Rng;
+use linux::{
+    cli::Args,
+    dir::Dir,
+    histogram::fmt_nanos,
+    names::{Order, Scheme},
+    report::{self, Record, Reporter},
+    runner::{self, RunConfig},
+};
 
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} <num_files> <target_directory> [--scheme sequential|random|long|prefix|fanout=N] [--seed N]",
+        program
+    );
+    eprintln!("       [--delete-order all|creation|sorted|reverse|shuffled[,...]]");
+    eprintln!("       [--threads N] [--layout shared|per-thread] [--remove-dir]");
+    eprintln!("       {} {}", report::USAGE, runner::USAGE);
+    eprintln!("Each thread creates and deletes its share of the files with openat/unlinkat,");
+    eprintln!("in the target directory (shared) or in a subdirectory of its own (per-thread)");
+    eprintln!("The target directory is created if missing and must be empty if it exists;");
+    eprintln!("it is kept afterwards unless --remove-dir is given. Files are removed again");
+    eprintln!("if a run fails or is interrupted with Ctrl-C");
+    process::exit(1);
+}
 
-fn generate_random_filenames(n: usize) -> Vec<String> {
-    let mut filenames = Vec::new();
-    let mut rng = rand::thread_rng();
+// Where the worker threads put their files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Layout {
+    Shared,
+    PerThread,
+}
 
-    while filenames.len() < n {
-        let num = rng.gen_range(0..1_000_1000);
-        let name = format!("x{:06}", num);
-        if !filenames.contains(&name) {
-        filenames.push(name);
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Layout::Shared => "shared",
+            Layout::PerThread => "per-thread",
+        })
     }
 }
 
+impl FromStr for Layout {
+    type Err = String;
 
-    filenames
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shared" => Ok(Layout::Shared),
+            "per-thread" => Ok(Layout::PerThread),
+            _ => Err(format!(
+                "unknown layout '{}' (expected shared or per-thread)",
+                s
+            )),
+        }
+    }
 }
 
+struct Options {
+    num_files: usize,
+    target_dir: PathBuf,
+    scheme: Scheme,
+    seed: u64,
+    orders: Vec<Order>,
+    threads: usize,
+    layout: Layout,
+    remove_dir: bool,
+}
 
-fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 3 {
-        eprintln!("Usage: {} <num_files> <target_directory>", args[0]);
-        std::process::exit(1);
+fn parse_args(mut args: Args) -> Result<Options, String> {
+    let scheme = args.parse("--scheme")?.unwrap_or(Scheme::Random);
+    let seed = args.parse("--seed")?.unwrap_or(0);
+    let orders = match args.value("--delete-order")?.as_deref() {
+        None => vec![Order::Sorted],
+        Some("all") => Order::ALL.to_vec(),
+        Some(list) => list
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    let threads = args.parse("--threads")?.unwrap_or(1);
+    if threads == 0 {
+        return Err("--threads must be at least 1".into());
+    }
+    let layout = args.parse("--layout")?.unwrap_or(Layout::Shared);
+    let remove_dir = args.flag("--remove-dir");
+    match args.finish()?.as_slice() {
+        [num_files, target_dir] => Ok(Options {
+            num_files: num_files
+                .parse()
+                .map_err(|e| format!("invalid number of files '{}': {}", num_files, e))?,
+            target_dir: PathBuf::from(target_dir),
+            scheme,
+            seed,
+            orders,
+            threads,
+            layout,
+            remove_dir,
+        }),
+        _ => Err("expected a number of files and a target directory".into()),
+    }
+}
+
+// Use `path` if it is an empty directory, or create it if it doesn't exist.
+// Anything already in it would skew the numbers and might collide with (or
+// worse, be deleted as) one of our names.
+fn prepare(path: &Path) -> Result<(), String> {
+    match fs::read_dir(path) {
+        Ok(mut entries) => match entries.next() {
+            None => Ok(()),
+            Some(Ok(_)) => Err(format!(
+                "{} is not empty; give a new or empty directory",
+                path.display()
+            )),
+            Some(Err(e)) => Err(format!("{}: {}", path.display(), e)),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            fs::create_dir(path).map_err(|e| format!("{}: {}", path.display(), e))
+        }
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn interrupted() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "interrupted")
+}
+
+// Run `f` on every name of every share, one thread per share, all starting
+// together. Returns the wall time from the first thread starting to the last
+// one done.
+// Workers stop early once `stop` is set.
+fn in_parallel(
+    shares: &[(&Dir, Vec<&String>)],
+    stop: &AtomicBool,
+    f: impl Fn(&Dir, &str) -> io::Result<()> + Sync,
+) -> io::Result<Duration> {
+    let gate = Barrier::new(shares.len() + 1);
+    thread::scope(|s| {
+        let handles: Vec<_> = shares
+            .iter()
+            .map(|(dir, names)| {
+                let (gate, f) = (&gate, &f);
+                s.spawn(move || {
+                    gate.wait();
+                    let start = Instant::now();
+                    names.iter().try_for_each(|name| {
+                        if stop.load(Ordering::Relaxed) {
+                            return Err(interrupted());
+                        }
+                        f(dir, name)
+                    })?;
+                    Ok((start, Instant::now()))
+                })
+            })
+            .collect();
+        gate.wait();
+        // the main thread can wake up after the workers got going, so each
+        // one times itself
+        let mut first: Option<Instant> = None;
+        let mut last: Option<Instant> = None;
+        for h in handles {
+            let (start, end) = h
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("worker thread panicked")))?;
+            first = Some(first.map_or(start, |f| f.min(start)));
+            last = Some(last.map_or(end, |l| l.max(end)));
+        }
+        Ok(match (first, last) {
+            (Some(first), Some(last)) => last - first,
+            _ => Duration::ZERO,
+        })
+    })
+}
+
+// What one cycle made inside the target directory. Dropped while still
+// armed, after an error or Ctrl-C, it removes all of it again as far as it
+// got: every name that may have been created, then the directories.
+struct Created<'a> {
+    top: &'a Dir,
+    layout: Layout,
+    // one per thread; all the target directory for a shared layout
+    dirs: Vec<Dir>,
+    parts: Vec<&'a [String]>,
+    workdirs: Vec<String>,
+    subdirs: Vec<String>,
+    armed: bool,
+}
+
+impl Created<'_> {
+    // The directories the scheme's subdirectories go in.
+    fn fanout_in(&self) -> Vec<&Dir> {
+        match self.layout {
+            Layout::Shared => vec![self.top],
+            Layout::PerThread => self.dirs.iter().collect(),
+        }
+    }
+
+    // Every directory made, as (parent, name), children first.
+    fn made_dirs(&self) -> Vec<(&Dir, &str)> {
+        let mut out = Vec::new();
+        for dir in self.fanout_in() {
+            out.extend(self.subdirs.iter().map(|sub| (dir, sub.as_str())));
+        }
+        out.extend(self.workdirs.iter().map(|w| (self.top, w.as_str())));
+        out
+    }
+
+    // Remove the directories once all the files are gone.
+    fn finish(mut self) -> io::Result<()> {
+        self.armed = false;
+        for (dir, name) in self.made_dirs() {
+            dir.rmdir(name)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Created<'_> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        for (dir, names) in self.dirs.iter().zip(&self.parts) {
+            for name in names.iter() {
+                let _ = dir.unlink(name);
+            }
+        }
+        for (dir, name) in self.made_dirs() {
+            let _ = dir.rmdir(name);
+        }
+    }
+}
+
+// One create/delete cycle in the (empty) target directory: create every
+// file, delete them all in `order`, leave the directory empty again.
+fn cycle(
+    opts: &Options,
+    top: &Dir,
+    filenames: &[String],
+    order: Order,
+    stop: &AtomicBool,
+    verbose: bool,
+) -> io::Result<(Duration, Duration)> {
+    if stop.load(Ordering::Relaxed) {
+        return Err(interrupted());
+    }
+
+    // thread i gets the i-th slice of the names, in its own directory or not
+    let per = filenames.len().div_ceil(opts.threads).max(1);
+    let parts: Vec<&[String]> = filenames.chunks(per).collect();
+    let mut made = Created {
+        top,
+        layout: opts.layout,
+        dirs: Vec::new(),
+        parts: Vec::new(),
+        workdirs: Vec::new(),
+        subdirs: Vec::new(),
+        armed: true,
+    };
+    for i in 0..parts.len() {
+        made.dirs.push(match opts.layout {
+            Layout::Shared => Dir::open(&opts.target_dir)?,
+            Layout::PerThread => {
+                let w = format!("t{}", i);
+                top.mkdir(&w)?;
+                made.workdirs.push(w);
+                top.open_dir(made.workdirs.last().unwrap())?
+            }
+        });
+    }
+    for sub in opts.scheme.dirs() {
+        for dir in made.fanout_in() {
+            dir.mkdir(&sub)?;
+        }
+        made.subdirs.push(sub);
+    }
+    made.parts = parts;
+
+    let creates: Vec<(&Dir, Vec<&String>)> = made
+        .dirs
+        .iter()
+        .zip(&made.parts)
+        .map(|(d, names)| (d, names.iter().collect()))
+        .collect();
+    let duration_create = in_parallel(&creates, stop, |dir, name| {
+        dir.create(name)?.write_all(&[0u8])
+    })?;
+    if verbose {
+        println!("created {} files in {:?}", filenames.len(), duration_create);
     }
-    
-    let num_files: usize = args[1].parse().expect("invalid number");
-    let target_dir = PathBuf::from(&args[2]);
 
-    //Ensure directory exists 
-    fs::create_dir(&target_dir)?;
+    //arrange each thread's filenames for deletion
+    let deletes: Vec<(&Dir, Vec<&String>)> = made
+        .dirs
+        .iter()
+        .zip(&made.parts)
+        .map(|(d, names)| (d, order.arrange(names, opts.seed)))
+        .collect();
+    let duration_delete = in_parallel(&deletes, stop, |dir, name| dir.unlink(name))?;
+    if verbose {
+        println!(
+            "Deleted {} files in {} order {:?}",
+            filenames.len(),
+            order,
+            duration_delete
+        );
+    }
 
-    //Generate unique random filenames
-    let filenames = generate_random_filenames(num_files);
+    made.finish()?;
+    Ok((duration_create, duration_delete))
+}
 
-    //start timer creation
-    let start_timer = Instant::now();
+fn main() -> io::Result<()> {
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs, opts) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            Ok((
+                reporter,
+                RunConfig::from_args(&mut args)?,
+                parse_args(args)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            usage(&program)
+        });
 
-    for filename in &filenames {
-        let path = target_dir.join(filename);
-        let mut file = File::create(&path)?;
-        file.write_all(&[0u8])?;
+    if let Err(e) = prepare(&opts.target_dir) {
+        eprintln!("{}", e);
+        process::exit(1);
     }
 
-    let duration_create = start_timer.elapsed();
-    println!("created {} files in {:?}", num_files, duration_create);
+    // from here on Ctrl-C stops the run cleanly instead of killing it
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
 
-    //sort filenames for deletion
-    let mut sorted_filenames = filenames.clone();
-    sorted_filenames.sort();
+    //Generate unique filenames, the same ones for every run
+    let start_generate = Instant::now();
+    let filenames = opts.scheme.generate(opts.num_files, opts.seed);
+    if reporter.wants_text() {
+        println!(
+            "generated {} {} names in {:?}",
+            opts.num_files,
+            opts.scheme,
+            start_generate.elapsed()
+        );
+        if opts.threads > 1 {
+            println!("{} threads, {} directory", opts.threads, opts.layout);
+        }
+    }
+
+    let top = Dir::open(&opts.target_dir)?;
 
-    //start timer deletio
-    let start_delete = Instant::now();
-    
-    for filename in &sorted_filenames {
-        let path = target_dir.join(filename);
-        fs::remove_file(&path)?;
+    // every order gets freshly created files to delete
+    let mut results = Vec::new();
+    let mut failed = None;
+    for &order in &opts.orders {
+        let cycles =
+            runs.run(|| cycle(&opts, &top, &filenames, order, &stop, reporter.wants_text()));
+        match cycles {
+            Ok(cycles) => results.extend(
+                cycles
+                    .into_iter()
+                    .map(|(create, delete)| (order, create, delete)),
+            ),
+            Err(e) => {
+                // the cycle has already removed whatever it created
+                eprintln!("{}: {}", opts.target_dir.display(), e);
+                failed = Some(e);
+                break;
+            }
+        }
+    }
+    drop(top);
+    if opts.remove_dir
+        && let Err(e) = fs::remove_dir(&opts.target_dir)
+    {
+        eprintln!("{}: {}", opts.target_dir.display(), e);
     }
 
-    let duration_delete = start_delete.elapsed();
-    println!("Deleted {} files {:?}", num_files, duration_delete);
+    if reporter.wants_text() && (runs.repeat > 1 || opts.orders.len() > 1) {
+        let label = |r: &(Order, Duration, Duration)| r.0.to_string();
+        runner::summarise(
+            "create",
+            &results,
+            runs.repeat,
+            label,
+            |r| r.1.as_nanos() as f64,
+            |v| fmt_nanos(v as u64),
+        );
+        runner::summarise(
+            "delete",
+            &results,
+            runs.repeat,
+            label,
+            |r| r.2.as_nanos() as f64,
+            |v| fmt_nanos(v as u64),
+        );
+    }
+
+    for (i, (order, create, delete)) in results.iter().enumerate() {
+        reporter.emit(
+            Record::new("random_files", &opts.target_dir)
+                .run(i % runs.repeat as usize)
+                .param("num_files", opts.num_files)
+                .param("scheme", opts.scheme.to_string())
+                .param("seed", opts.seed)
+                .param("delete_order", order.name())
+                .param("threads", opts.threads)
+                .param("layout", opts.layout.to_string())
+                .result("create_ns", create.as_nanos() as u64)
+                .result("delete_ns", delete.as_nanos() as u64),
+        )?;
+    }
 
-    Ok(())
-}
\ No newline at end of file
+    match failed {
+        Some(e) if e.kind() == io::ErrorKind::Interrupted => process::exit(130),
+        Some(_) => process::exit(1),
+        None => Ok(()),
+    }
+}
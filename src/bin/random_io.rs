@@ -0,0 +1,119 @@
+use std::process;
+
+use linux::{
+    bench::workload::WorkloadReport,
+    bench::workload::{self, WorkloadConfig},
+    cli::Args,
+    histogram::fmt_nanos,
+    report::{self, Reporter},
+    runner::{self, RunConfig},
+};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [--pattern random|sequential|stride=BYTES] [--read-percent 0-100] [--ops N] [--block-size BYTES] [--file-size BYTES] [--seed N] [--direct] [--path FILE]",
+        program
+    );
+    eprintln!("       {} {}", report::USAGE, runner::USAGE);
+    eprintln!(
+        "Issues pread/pwrite at seeded offsets inside a preallocated file (default workload.dat, 64M)"
+    );
+    process::exit(1);
+}
+
+fn parse_args(mut args: Args) -> Result<WorkloadConfig, String> {
+    let mut cfg = WorkloadConfig::default();
+
+    if let Some(pattern) = args.parse("--pattern")? {
+        cfg.pattern = pattern;
+    }
+    if let Some(percent) = args.parse::<u8>("--read-percent")? {
+        if percent > 100 {
+            return Err("--read-percent must be between 0 and 100".into());
+        }
+        cfg.read_percent = percent;
+    }
+    if let Some(ops) = args.parse("--ops")? {
+        cfg.ops = ops;
+    }
+    if let Some(block) = args.size("--block-size")? {
+        cfg.block_size = block as usize;
+    }
+    if let Some(size) = args.size("--file-size")? {
+        cfg.file_size = size;
+    }
+    if let Some(seed) = args.parse("--seed")? {
+        cfg.seed = seed;
+    }
+    cfg.direct = args.flag("--direct");
+    if let Some(path) = args.value("--path")? {
+        cfg.path = path.into();
+    }
+
+    if !args.finish()?.is_empty() {
+        return Err("unexpected positional arguments".into());
+    }
+    Ok(cfg)
+}
+
+fn main() {
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs, cfg) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            Ok((
+                reporter,
+                RunConfig::from_args(&mut args)?,
+                parse_args(args)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            usage(&program)
+        });
+
+    if reporter.wants_text() {
+        println!(
+            "{} {}% reads, {} x {} bytes in {} ({} bytes), seed {}{}\n",
+            cfg.pattern,
+            cfg.read_percent,
+            cfg.ops,
+            cfg.block_size,
+            cfg.path.display(),
+            cfg.file_size,
+            cfg.seed,
+            if cfg.direct { ", O_DIRECT" } else { "" }
+        );
+    }
+
+    // the same seed every run, so repeats replay the same offsets
+    let reports = runs.run(|| workload::run(&cfg)).unwrap_or_else(|e| {
+        eprintln!("{}: {}", cfg.path.display(), e);
+        process::exit(1);
+    });
+    if reporter.wants_text() {
+        if let [report] = reports.as_slice() {
+            workload::print_report(report);
+        } else {
+            let label = |_: &WorkloadReport| cfg.pattern.to_string();
+            let time = |r: &WorkloadReport| r.elapsed.as_nanos() as f64;
+            runner::summarise("time", &reports, runs.repeat, label, time, |v| {
+                fmt_nanos(v as u64)
+            });
+            runner::summarise(
+                "IOPS",
+                &reports,
+                runs.repeat,
+                label,
+                |r| r.iops(),
+                |v| format!("{:.0}", v),
+            );
+        }
+    }
+    for (i, report) in reports.iter().enumerate() {
+        if let Err(e) = reporter.emit(report.record(&cfg).run(i)) {
+            eprintln!("failed to write results: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::process;
+
+use linux::{
+    bench::read::{self, CacheState, ReadConfig, ReadStrategy},
+    cli::Args,
+    histogram::fmt_nanos,
+    report::{self, Reporter},
+    runner::{self, RunConfig},
+};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [--strategy all|buffered|raw|direct|mmap] [--cache cold,warm] [--block-size BYTES] [--hint HINT] [--path FILE]",
+        program
+    );
+    eprintln!("       {} {}", report::USAGE, runner::USAGE);
+    eprintln!("Hints: none, sequential, random, willneed, noreuse, readahead");
+    eprintln!(
+        "Reads a file produced by write_bench, buffered_write or direct_IO (default bench.txt)"
+    );
+    process::exit(1);
+}
+
+fn parse_list<T>(value: Option<String>, all: &[T]) -> Result<Vec<T>, String>
+where
+    T: Copy + std::str::FromStr<Err = String>,
+{
+    match value.as_deref() {
+        None | Some("all") => Ok(all.to_vec()),
+        Some(list) => list.split(',').map(str::parse).collect(),
+    }
+}
+
+fn parse_args(mut args: Args) -> Result<(Vec<ReadStrategy>, Vec<CacheState>, ReadConfig), String> {
+    let mut cfg = ReadConfig::default();
+
+    let strategies = parse_list(args.value("--strategy")?, &ReadStrategy::ALL)?;
+    let caches = parse_list(
+        args.value("--cache")?,
+        &[CacheState::Cold, CacheState::Warm],
+    )?;
+    if let Some(block) = args.size("--block-size")? {
+        cfg.block_size = block as usize;
+    }
+    if let Some(hint) = args.parse("--hint")? {
+        cfg.hint = hint;
+    }
+    if let Some(path) = args.value("--path")? {
+        cfg.path = path.into();
+    }
+
+    if !args.finish()?.is_empty() {
+        return Err("unexpected positional arguments".into());
+    }
+    Ok((strategies, caches, cfg))
+}
+
+fn main() {
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs, (strategies, caches, cfg)) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            Ok((
+                reporter,
+                RunConfig::from_args(&mut args)?,
+                parse_args(args)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            usage(&program)
+        });
+
+    if reporter.wants_text() {
+        println!(
+            "reading {} in {}-byte blocks, hint {}\n",
+            cfg.path.display(),
+            cfg.block_size,
+            cfg.hint
+        );
+    }
+
+    let mut reports = Vec::new();
+    for &strategy in &strategies {
+        for &cache in &caches {
+            match runs.run(|| read::run(strategy, cache, &cfg)) {
+                Ok(r) => reports.extend(r),
+                Err(e) => eprintln!("{} ({}): {}", strategy, cache.name(), e),
+            }
+        }
+    }
+
+    if reporter.wants_text() {
+        read::print_table(&reports);
+        if runs.repeat > 1 {
+            let label = |r: &read::ReadReport| format!("{} {}", r.strategy, r.cache.name());
+            let time = |r: &read::ReadReport| r.elapsed.as_nanos() as f64;
+            runner::summarise("time", &reports, runs.repeat, label, time, |v| {
+                fmt_nanos(v as u64)
+            });
+            runner::summarise(
+                "MiB/s",
+                &reports,
+                runs.repeat,
+                label,
+                |r| r.throughput_mib(),
+                |v| format!("{:.1}", v),
+            );
+        }
+        if let Some(eviction) = reports.iter().find_map(|r| r.eviction) {
+            println!("\ncold runs evicted the cache with {:?}", eviction);
+        }
+    }
+    for (i, report) in reports.iter().enumerate() {
+        let record = report.record(&cfg).run(i % runs.repeat as usize);
+        if let Err(e) = reporter.emit(record) {
+            eprintln!("failed to write results: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -1,20 +1,207 @@
-use nix::fcntl::{open, OFlag};
+use std::io::{self, Write};
+use std::os::fd::OwnedFd;
+use std::process;
+
+use nix::errno::Errno;
+use nix::fcntl::{OFlag, open};
 use nix::sys::stat::Mode;
+use nix::sys::uio::pread;
 use nix::unistd::read;
 
+use linux::{cli::Args, instrument};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Hex,
+    Count,
+}
+
+struct Options {
+    path: String,
+    offset: Option<u64>,
+    length: Option<u64>,
+    buf_size: usize,
+    format: Format,
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [path] [--offset BYTES] [--length BYTES] [--buf-size BYTES] [--format text|hex|count]",
+        program
+    );
+    eprintln!("path defaults to /etc/hostname; --offset reads with pread instead of read");
+    process::exit(1);
+}
+
+fn parse_args(mut args: Args) -> Result<Options, String> {
+    let offset = args.size("--offset")?;
+    let length = args.size("--length")?;
+    let buf_size = args.size("--buf-size")?.unwrap_or(128) as usize;
+    let format = match args.value("--format")?.as_deref() {
+        None | Some("text") => Format::Text,
+        Some("hex") => Format::Hex,
+        Some("count") => Format::Count,
+        Some(other) => return Err(format!("unknown format '{}'", other)),
+    };
+    let path = match args.finish()?.as_slice() {
+        [] => "/etc/hostname".to_string(),
+        [path] => path.clone(),
+        _ => return Err("expected at most one path".into()),
+    };
+    if buf_size == 0 {
+        return Err("--buf-size must be non-zero".into());
+    }
+    Ok(Options {
+        path,
+        offset,
+        length,
+        buf_size,
+        format,
+    })
+}
+
 fn main() {
+    let _summary = instrument::enable();
+
+    let args = Args::from_env();
+    let program = args.program().to_string();
+    let opts = parse_args(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        usage(&program)
+    });
+
     // Open the file - returns OwnedFd
-    let file = open("/etc/hostname", OFlag::O_RDONLY, Mode::empty())
-        .expect("Failed to open file");
-    
-    let mut buffer = [0u8; 128];
-    // Use as_raw_fd() to get the raw descriptor when needed
-    let bytes_read = read(file, &mut buffer)
-        .expect("Failed to read file");
-    
-    println!("Read {} bytes: {}", 
-        bytes_read, 
-        String::from_utf8_lossy(&buffer[..bytes_read]));
-    
+    let file = instrument::timed("open", || {
+        open(opts.path.as_str(), OFlag::O_RDONLY, Mode::empty())
+    })
+    .unwrap_or_else(|e| {
+        eprintln!("Failed to open {}: {}", opts.path, e);
+        process::exit(1);
+    });
+
+    let stdout = io::stdout();
+    let mut out = Output::new(opts.format, opts.offset.unwrap_or(0), stdout.lock());
+
+    if let Err(e) = copy(&file, &opts, &mut out) {
+        eprintln!("Failed to read {}: {}", opts.path, e);
+        process::exit(1);
+    }
+    if let Err(e) = out.finish() {
+        eprintln!("Failed to write output: {}", e);
+        process::exit(1);
+    }
+
     // No need to explicitly close - OwnedFd implements Drop
-}
\ No newline at end of file
+}
+
+// Read until EOF (or --length bytes), however many calls that takes.
+// read(2) may return fewer bytes than asked for and fail with EINTR when a
+// signal arrives; neither means we're done.
+fn copy(file: &OwnedFd, opts: &Options, out: &mut Output<impl Write>) -> io::Result<()> {
+    let mut buffer = vec![0u8; opts.buf_size];
+    let mut remaining = opts.length.unwrap_or(u64::MAX);
+    let mut position = opts.offset;
+
+    while remaining > 0 {
+        let want = remaining.min(buffer.len() as u64) as usize;
+        let result = match position {
+            // pread doesn't move the file offset, so we track it ourselves
+            Some(pos) => {
+                instrument::timed("pread", || pread(file, &mut buffer[..want], pos as i64))
+            }
+            None => instrument::timed("read", || read(file, &mut buffer[..want])),
+        };
+        let bytes_read = match result {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        out.write(&buffer[..bytes_read])?;
+        remaining -= bytes_read as u64;
+        if let Some(pos) = position.as_mut() {
+            *pos += bytes_read as u64;
+        }
+    }
+    Ok(())
+}
+
+struct Output<W: Write> {
+    format: Format,
+    out: W,
+    // hex: file offset of `line`, and the bytes of the current partial line
+    offset: u64,
+    line: Vec<u8>,
+    total: u64,
+}
+
+impl<W: Write> Output<W> {
+    fn new(format: Format, offset: u64, out: W) -> Self {
+        Self {
+            format,
+            out,
+            offset,
+            line: Vec::with_capacity(16),
+            total: 0,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.total += bytes.len() as u64;
+        match self.format {
+            Format::Text => self.out.write_all(bytes),
+            Format::Count => Ok(()),
+            Format::Hex => {
+                for &b in bytes {
+                    self.line.push(b);
+                    if self.line.len() == 16 {
+                        self.hex_line()?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // 00000010  68 65 6c 6c 6f 0a                                 |hello.|
+    fn hex_line(&mut self) -> io::Result<()> {
+        let mut hex = String::with_capacity(49);
+        for (i, b) in self.line.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", b));
+        }
+        let ascii: String = self
+            .line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(self.out, "{:08x}  {:<49} |{}|", self.offset, hex, ascii)?;
+        self.offset += self.line.len() as u64;
+        self.line.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        match self.format {
+            Format::Text => {}
+            Format::Hex => {
+                if !self.line.is_empty() {
+                    self.hex_line()?;
+                }
+                writeln!(self.out, "{:08x}", self.offset)?;
+            }
+            Format::Count => writeln!(self.out, "{}", self.total)?,
+        }
+        self.out.flush()
+    }
+}
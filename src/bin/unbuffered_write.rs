@@ -1,15 +1,86 @@
-use std::{fs::OpenOptions, io::Write};
+use std::{fs::OpenOptions, io::Write, process, time::Instant};
+
+use linux::{
+    cli::Args,
+    histogram::fmt_nanos,
+    instrument,
+    report::{self, Record, Reporter},
+    runner::{self, RunConfig},
+    sparse::Prealloc,
+};
+
+const SIZE: u64 = 100_000_000;
+
+fn parse_args(mut args: Args) -> Result<Prealloc, String> {
+    let prealloc = args.parse("--prealloc")?.unwrap_or_default();
+    if !args.finish()?.is_empty() {
+        return Err("unexpected positional arguments".into());
+    }
+    Ok(prealloc)
+}
 
 fn main() -> std::io::Result<()> {
-    let mut file = OpenOptions::new()
-    .write(true)
-    .create(true)
-    .truncate(true)
-    .open("no_buffer.txt")?;
-
-    for _ in 0..100_000_000 {
-        file.write_all(b"A")?;
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs, prealloc) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            Ok((
+                reporter,
+                RunConfig::from_args(&mut args)?,
+                parse_args(args)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            eprintln!(
+                "Usage: {} [--prealloc none|allocate|keep-size] {} {}",
+                program,
+                report::USAGE,
+                runner::USAGE
+            );
+            process::exit(1);
+        });
+
+    // count every write(2) and print what they cost when main returns
+    let _summary = instrument::enable();
+
+    let records = runs.run(|| -> std::io::Result<_> {
+        // each run's record (and the summary, for the last run) only counts its own calls
+        instrument::reset();
+        let start = Instant::now();
+
+        let mut file = instrument::open(
+            OpenOptions::new().write(true).create(true).truncate(true),
+            "no_buffer.txt",
+        )?;
+        // reserve all the blocks up front instead of growing the file a byte at a time
+        if prealloc != Prealloc::None {
+            instrument::timed("fallocate", || prealloc.apply(file.get_ref(), SIZE))?;
+        }
+
+        for _ in 0..SIZE {
+            file.write_all(b"A")?;
+        }
+        drop(file);
+        let elapsed = start.elapsed();
+
+        let record = instrument::add_to(
+            Record::new("unbuffered_write", "no_buffer.txt")
+                .param("size", SIZE)
+                .param("prealloc", prealloc.to_string())
+                .elapsed(elapsed),
+        );
+        Ok((elapsed, record))
+    })?;
+
+    if runs.repeat > 1 && reporter.wants_text() {
+        let times: Vec<f64> = records.iter().map(|(t, _)| t.as_nanos() as f64).collect();
+        runner::print_summary("time", &[("unbuffered".into(), times)], |v| {
+            fmt_nanos(v as u64)
+        });
+    }
+    for (i, (_, record)) in records.into_iter().enumerate() {
+        reporter.emit(record.run(i))?;
     }
-    
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,142 @@
+use std::process;
+
+use linux::{
+    bench::{self, Durability, Strategy, WriteConfig, vectored},
+    cli::Args,
+    histogram::fmt_nanos,
+    report::{self, Reporter},
+    runner::{self, RunConfig},
+};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [--strategy all|buffered|unbuffered|direct|uring|vectored|mmap] [--size BYTES] [--block-size BYTES] [--path FILE] [--prealloc none|allocate|keep-size]",
+        program
+    );
+    eprintln!(
+        "       [--durability MODE[,MODE...]]  modes: {}",
+        Durability::EXAMPLES
+    );
+    eprintln!("       io_uring: [--queue-depth N] [--register-buffers] [--fixed-files]");
+    eprintln!("       vectored: [--iov-batch N] [--rwf hipri,dsync,sync,nowait,append]");
+    eprintln!("       mmap:     [--msync none|async|sync] [--madvise ADVICE]");
+    eprintln!("       output:   {} {}", report::USAGE, runner::USAGE);
+    eprintln!("Sizes accept K/M/G suffixes, e.g. --size 64M --block-size 4K");
+    process::exit(1);
+}
+
+fn parse_args(mut args: Args) -> Result<(Vec<Strategy>, Vec<Durability>, WriteConfig), String> {
+    let mut cfg = WriteConfig::default();
+
+    let strategies = match args.value("--strategy")?.as_deref() {
+        None | Some("all") => Strategy::ALL.to_vec(),
+        Some(list) => list
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    let durabilities = match args.value("--durability")? {
+        None => vec![Durability::None],
+        Some(list) => list
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    if let Some(size) = args.size("--size")? {
+        cfg.total_size = size;
+    }
+    if let Some(block) = args.size("--block-size")? {
+        cfg.block_size = block as usize;
+    }
+    if let Some(path) = args.value("--path")? {
+        cfg.path = path.into();
+    }
+    if let Some(prealloc) = args.parse("--prealloc")? {
+        cfg.prealloc = prealloc;
+    }
+    if let Some(depth) = args.parse("--queue-depth")? {
+        cfg.uring.queue_depth = depth;
+    }
+    cfg.uring.register_buffers = args.flag("--register-buffers");
+    cfg.uring.fixed_files = args.flag("--fixed-files");
+    if let Some(batch) = args.parse("--iov-batch")? {
+        cfg.vectored.batch = batch;
+    }
+    if let Some(flags) = args.value("--rwf")? {
+        cfg.vectored.flags = vectored::parse_rwf_flags(&flags)?;
+    }
+    if let Some(msync) = args.parse("--msync")? {
+        cfg.mmap.msync = msync;
+    }
+    cfg.mmap.advice = args.parse("--madvise")?;
+
+    if !args.finish()?.is_empty() {
+        return Err("unexpected positional arguments".into());
+    }
+    Ok((strategies, durabilities, cfg))
+}
+
+fn main() {
+    let mut args = Args::from_env();
+    let program = args.program().to_string();
+    let (mut reporter, runs, (strategies, durabilities, mut cfg)) = Reporter::from_args(&mut args)
+        .and_then(|reporter| {
+            Ok((
+                reporter,
+                RunConfig::from_args(&mut args)?,
+                parse_args(args)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            usage(&program)
+        });
+
+    if reporter.wants_text() {
+        println!(
+            "writing {} bytes in {}-byte blocks to {}, preallocation {}\n",
+            cfg.total_size,
+            cfg.block_size,
+            cfg.path.display(),
+            cfg.prealloc
+        );
+    }
+
+    // runs.repeat reports per configuration, one after the other
+    let mut reports = Vec::new();
+    for &strategy in &strategies {
+        for &durability in &durabilities {
+            cfg.durability = durability;
+            match runs.run(|| bench::run(strategy, &cfg)) {
+                Ok(r) => reports.extend(r),
+                Err(e) => eprintln!("{} ({}): {}", strategy, durability, e),
+            }
+        }
+    }
+
+    if reporter.wants_text() {
+        bench::print_table(&reports);
+        if runs.repeat > 1 {
+            let label = |r: &bench::WriteReport| format!("{} {}", r.strategy, r.durability);
+            let time = |r: &bench::WriteReport| r.elapsed.as_nanos() as f64;
+            runner::summarise("time", &reports, runs.repeat, label, time, |v| {
+                fmt_nanos(v as u64)
+            });
+            runner::summarise(
+                "MiB/s",
+                &reports,
+                runs.repeat,
+                label,
+                |r| r.throughput_mib(),
+                |v| format!("{:.1}", v),
+            );
+        }
+    }
+    for (i, report) in reports.iter().enumerate() {
+        let record = report.record(&cfg).run(i % runs.repeat as usize);
+        if let Err(e) = reporter.emit(record) {
+            eprintln!("failed to write results: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// Page cache control for benchmarks that need a known starting state, and
+// a look at what's in the cache for a given file.
+
+use std::{
+    fs::{self, File},
+    io,
+    os::unix::io::AsRawFd,
+    path::Path,
+};
+
+use crate::mmap::Mapping;
+
+/// How the cache was emptied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eviction {
+    /// posix_fadvise(POSIX_FADV_DONTNEED) on the one file.
+    Fadvise,
+    /// echo 1 > /proc/sys/vm/drop_caches, for everything (root only).
+    DropCaches,
+}
+
+/// Push `path` out of the page cache.
+///
+/// Dirty pages can't be dropped, so the file is synced first. As root this
+/// also drops the whole page cache, which catches pages DONTNEED leaves
+/// behind (e.g. ones mapped by another process); otherwise DONTNEED is all we
+/// can do.
+pub fn evict<P: AsRef<Path>>(path: P) -> io::Result<Eviction> {
+    let file = File::open(path)?;
+    file.sync_data()?;
+    fadvise(&file, 0, 0, libc::POSIX_FADV_DONTNEED)?;
+
+    if unsafe { libc::geteuid() } == 0 && fs::write("/proc/sys/vm/drop_caches", "1").is_ok() {
+        return Ok(Eviction::DropCaches);
+    }
+    Ok(Eviction::Fadvise)
+}
+
+/// posix_fadvise(2) over `len` bytes from `offset` (0 = to end of file).
+pub fn fadvise(file: &File, offset: u64, len: u64, advice: i32) -> io::Result<()> {
+    let rc = unsafe { libc::posix_fadvise(file.as_raw_fd(), offset as i64, len as i64, advice) };
+    // posix_fadvise returns the error instead of setting errno
+    if rc != 0 {
+        return Err(io::Error::from_raw_os_error(rc));
+    }
+    Ok(())
+}
+
+/// readahead(2): start reading `len` bytes from `offset` into the cache.
+pub fn readahead(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    let rc = unsafe { libc::readahead(file.as_raw_fd(), offset as i64, len as usize) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Which pages of a file are in the page cache, from mincore(2).
+pub struct Residency {
+    pub file_size: u64,
+    pub page_size: usize,
+    /// One entry per page, true if resident.
+    pub pages: Vec<bool>,
+}
+
+impl Residency {
+    pub fn resident(&self) -> usize {
+        self.pages.iter().filter(|&&r| r).count()
+    }
+
+    pub fn resident_bytes(&self) -> u64 {
+        (self.resident() as u64 * self.page_size as u64).min(self.file_size)
+    }
+
+    pub fn percent(&self) -> f64 {
+        if self.pages.is_empty() {
+            0.0
+        } else {
+            self.resident() as f64 * 100.0 / self.pages.len() as f64
+        }
+    }
+
+    /// `width` characters, one per run of pages: '#' all resident, '+' some,
+    /// '.' none.
+    pub fn map(&self, width: usize) -> String {
+        if self.pages.is_empty() || width == 0 {
+            return String::new();
+        }
+        let per = self.pages.len().div_ceil(width);
+        self.pages
+            .chunks(per)
+            .map(|run| match run.iter().filter(|&&r| r).count() {
+                0 => '.',
+                n if n == run.len() => '#',
+                _ => '+',
+            })
+            .collect()
+    }
+}
+
+/// Map `path` and ask mincore(2) which of its pages are cached.
+///
+/// Mapping the file doesn't fault anything in, so looking doesn't change the
+/// answer.
+pub fn residency<P: AsRef<Path>>(path: P) -> io::Result<Residency> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    if file_size == 0 {
+        return Ok(Residency {
+            file_size,
+            page_size,
+            pages: Vec::new(),
+        });
+    }
+
+    let map = Mapping::read_only(&file, file_size as usize)?;
+    let mut vec = vec![0u8; map.len().div_ceil(page_size)];
+    let rc = unsafe { libc::mincore(map.as_ptr() as *mut _, map.len(), vec.as_mut_ptr()) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Residency {
+        file_size,
+        page_size,
+        // only the low bit is defined, the rest is reserved
+        pages: vec.iter().map(|&b| b & 1 != 0).collect(),
+    })
+}
+
+/// The system-wide page cache counters from /proc/meminfo, in bytes.
+///
+/// The kernel doesn't export dirty pages per file, so Dirty and Writeback are
+/// the best estimate of how much of a freshly written file hasn't hit the
+/// disk yet.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemInfo {
+    pub cached: u64,
+    pub dirty: u64,
+    pub writeback: u64,
+}
+
+pub fn meminfo() -> io::Result<MemInfo> {
+    let text = fs::read_to_string("/proc/meminfo")?;
+    let mut info = MemInfo::default();
+    for line in text.lines() {
+        let Some((key, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let field = match key {
+            "Cached" => &mut info.cached,
+            "Dirty" => &mut info.dirty,
+            "Writeback" => &mut info.writeback,
+            _ => continue,
+        };
+        // "Dirty:             1234 kB"
+        let kb = rest
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse::<u64>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", key, e)))?;
+        *field = kb * 1024;
+    }
+    Ok(info)
+}
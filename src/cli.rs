@@ -0,0 +1,101 @@
+use std::{env, fmt::Display, str::FromStr};
+
+/// Tiny pull-style argument parser shared by the benchmark binaries.
+///
+/// Options are taken out of the argument list as they are asked for, so
+/// whatever is left at the end is either positional or a typo.
+pub struct Args {
+    program: String,
+    rest: Vec<String>,
+}
+
+impl Args {
+    pub fn from_env() -> Self {
+        let mut args = env::args();
+        let program = args.next().unwrap_or_default();
+        Self {
+            program,
+            rest: args.collect(),
+        }
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    /// Remove a boolean `--name` switch, returning whether it was present.
+    pub fn flag(&mut self, name: &str) -> bool {
+        let before = self.rest.len();
+        self.rest.retain(|arg| arg != name);
+        self.rest.len() != before
+    }
+
+    /// Remove `--name value` or `--name=value` and return the value.
+    pub fn value(&mut self, name: &str) -> Result<Option<String>, String> {
+        let prefix = format!("{}=", name);
+        let Some(pos) = self
+            .rest
+            .iter()
+            .position(|arg| arg == name || arg.starts_with(&prefix))
+        else {
+            return Ok(None);
+        };
+
+        let arg = self.rest.remove(pos);
+        if let Some(value) = arg.strip_prefix(&prefix) {
+            return Ok(Some(value.to_string()));
+        }
+        if pos < self.rest.len() {
+            Ok(Some(self.rest.remove(pos)))
+        } else {
+            Err(format!("{} needs a value", name))
+        }
+    }
+
+    /// Like [`Args::value`], parsed with `FromStr`.
+    pub fn parse<T>(&mut self, name: &str) -> Result<Option<T>, String>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.value(name)? {
+            Some(raw) => raw
+                .parse()
+                .map(Some)
+                .map_err(|e| format!("invalid {} '{}': {}", name, raw, e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`Args::value`], parsed with [`parse_size`].
+    pub fn size(&mut self, name: &str) -> Result<Option<u64>, String> {
+        match self.value(name)? {
+            Some(raw) => parse_size(&raw)
+                .map(Some)
+                .map_err(|e| format!("invalid {} '{}': {}", name, raw, e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Whatever is left once all options were taken. Leftover `--options` are errors.
+    pub fn finish(self) -> Result<Vec<String>, String> {
+        if let Some(unknown) = self.rest.iter().find(|arg| arg.starts_with("--")) {
+            return Err(format!("unknown option {}", unknown));
+        }
+        Ok(self.rest)
+    }
+}
+
+/// Parse a byte count such as `4096`, `64K`, `8M` or `1G` (powers of 1024).
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, shift) = match s.as_bytes().last() {
+        Some(b'k' | b'K') => (&s[..s.len() - 1], 10),
+        Some(b'm' | b'M') => (&s[..s.len() - 1], 20),
+        Some(b'g' | b'G') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let n: u64 = digits.parse().map_err(|e| format!("{}", e))?;
+    n.checked_mul(1 << shift)
+        .ok_or_else(|| "size overflows u64".to_string())
+}
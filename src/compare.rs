@@ -0,0 +1,143 @@
+// Line up two sets of saved results (say before and after a kernel upgrade
+// or a mount option change) and tell which metrics moved.
+//
+// Records are matched on benchmark name and parameters; the repetitions of a
+// configuration in each file are the samples. A metric is a regression when
+// it got worse by more than the threshold and Welch's t-test says the change
+// is more than noise.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    report::Saved,
+    runner::{self, Summary},
+};
+
+/// Which way is better for a metric.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Higher,
+    Lower,
+}
+
+impl Direction {
+    /// Guess from the name: throughput and IOPS should go up; times,
+    /// latencies, syscall and fault counts should go down. Anything else
+    /// (byte counts, checksums...) isn't a performance metric.
+    pub fn of(metric: &str) -> Option<Self> {
+        if metric.contains("throughput") || metric.contains("iops") {
+            Some(Direction::Higher)
+        } else if metric.ends_with("_ns") || metric.ends_with("faults") || metric == "syscalls" {
+            Some(Direction::Lower)
+        } else {
+            None
+        }
+    }
+}
+
+/// One metric of one configuration, before and after.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub benchmark: String,
+    pub params: BTreeMap<String, String>,
+    pub metric: String,
+    pub direction: Direction,
+    pub before: Summary,
+    pub after: Summary,
+    /// From [`runner::significant`]; None with a single run on either side.
+    pub significant: Option<bool>,
+}
+
+impl Comparison {
+    /// Change of the mean, in percent of the old one.
+    pub fn change_percent(&self) -> f64 {
+        if self.before.mean == 0.0 {
+            if self.after.mean == 0.0 {
+                0.0
+            } else {
+                f64::INFINITY
+            }
+        } else {
+            (self.after.mean - self.before.mean) / self.before.mean.abs() * 100.0
+        }
+    }
+
+    /// How much worse the metric got, in percent; negative if it improved.
+    pub fn worse_percent(&self) -> f64 {
+        match self.direction {
+            Direction::Higher => -self.change_percent(),
+            Direction::Lower => self.change_percent(),
+        }
+    }
+
+    /// Worse by more than `threshold` percent, and not plausibly noise. With
+    /// too few runs to test, the threshold alone decides.
+    pub fn is_regression(&self, threshold: f64) -> bool {
+        self.significant != Some(false) && self.worse_percent() > threshold
+    }
+
+    pub fn is_improvement(&self, threshold: f64) -> bool {
+        self.significant != Some(false) && -self.worse_percent() > threshold
+    }
+}
+
+/// Compare every configuration present in both sets. `metrics` limits the
+/// comparison to those names (taken as lower-is-better unless
+/// [`Direction::of`] knows otherwise); empty means every metric with a known
+/// direction.
+pub fn compare(before: &[Saved], after: &[Saved], metrics: &[String]) -> Vec<Comparison> {
+    let before = group(before);
+    let after = group(after);
+
+    let mut out = Vec::new();
+    for (key, old) in &before {
+        let Some(new) = after.get(key) else {
+            continue;
+        };
+        let names: Vec<&String> = old[0]
+            .results
+            .keys()
+            .filter(|m| metrics.is_empty() || metrics.contains(m))
+            .collect();
+        for metric in names {
+            let direction = match Direction::of(metric) {
+                Some(d) => d,
+                None if !metrics.is_empty() => Direction::Lower,
+                None => continue,
+            };
+            let samples = |records: &[&Saved]| -> Vec<f64> {
+                records
+                    .iter()
+                    .filter_map(|r| r.results.get(metric).copied())
+                    .collect()
+            };
+            let (Some(b), Some(a)) = (Summary::of(&samples(old)), Summary::of(&samples(new)))
+            else {
+                continue;
+            };
+            out.push(Comparison {
+                benchmark: key.0.clone(),
+                params: key.1.clone(),
+                metric: metric.clone(),
+                direction,
+                significant: runner::significant(&b, &a),
+                before: b,
+                after: a,
+            });
+        }
+    }
+    out
+}
+
+type Key = (String, BTreeMap<String, String>);
+
+fn group(records: &[Saved]) -> BTreeMap<Key, Vec<&Saved>> {
+    let mut groups: BTreeMap<Key, Vec<&Saved>> = BTreeMap::new();
+    for r in records {
+        groups
+            .entry((r.benchmark.clone(), r.params.clone()))
+            .or_default()
+            .push(r);
+    }
+    groups
+}
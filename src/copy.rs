@@ -0,0 +1,336 @@
+// Copying a file four ways, from "everything through userspace" to "the
+// filesystem may not copy anything at all":
+//
+//   read-write       read(2) into a buffer, write(2) it out
+//   sendfile         sendfile(2), file to file, inside the kernel
+//   splice           splice(2) into a pipe and back out, no userspace copy
+//   copy-file-range  copy_file_range(2); btrfs/xfs can reflink instead of copying
+//
+// Short transfers and EINTR are retried for all of them.
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Read},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::fs::{FileExt, MetadataExt},
+    },
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{bench::lookup, report::Record, sparse};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    ReadWrite,
+    Sendfile,
+    Splice,
+    CopyFileRange,
+}
+
+impl Method {
+    pub const ALL: [Method; 4] = [
+        Method::ReadWrite,
+        Method::Sendfile,
+        Method::Splice,
+        Method::CopyFileRange,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::ReadWrite => "read-write",
+            Method::Sendfile => "sendfile",
+            Method::Splice => "splice",
+            Method::CopyFileRange => "copy-file-range",
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lookup(&Method::ALL, s, |m| m.name(), "copy method")
+    }
+}
+
+pub struct CopyReport {
+    pub method: Method,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub syscalls: u64,
+    /// Whether the copy shares extents with the source (a reflink);
+    /// None if the filesystem can't tell us.
+    pub shared: Option<bool>,
+}
+
+impl CopyReport {
+    pub fn throughput_mib(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.bytes as f64 / (1024.0 * 1024.0) / secs
+        }
+    }
+
+    pub fn record<P: AsRef<Path>>(&self, dst: P, chunk: usize) -> Record {
+        Record::new("copy_bench", dst)
+            .param("method", self.method.name())
+            .param("chunk", chunk)
+            .result("bytes", self.bytes)
+            .elapsed(self.elapsed)
+            .result("throughput_mib_s", self.throughput_mib())
+            .result("syscalls", self.syscalls)
+            .result("reflink", self.shared)
+    }
+}
+
+/// Copy `src` to `dst` (created or truncated) with `method`, moving at most
+/// `chunk` bytes per call.
+pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(
+    method: Method,
+    src: P,
+    dst: Q,
+    chunk: usize,
+) -> io::Result<CopyReport> {
+    if chunk == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "chunk size must be non-zero",
+        ));
+    }
+    let input = File::open(src)?;
+    let st = input.metadata()?;
+    let len = st.len();
+    // truncating dst would empty src before a byte of it was read
+    if let Ok(out) = fs::metadata(&dst)
+        && (out.dev(), out.ino()) == (st.dev(), st.ino())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "source and destination are the same file",
+        ));
+    }
+    let output = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dst)?;
+
+    let start = Instant::now();
+    let syscalls = match method {
+        Method::ReadWrite => read_write(&input, &output, chunk)?,
+        Method::Sendfile => sendfile(&input, &output, len, chunk)?,
+        Method::Splice => splice(&input, &output, len, chunk)?,
+        Method::CopyFileRange => copy_file_range(&input, &output, len, chunk)?,
+    };
+    let elapsed = start.elapsed();
+
+    let copied = output.metadata()?.len();
+    if copied != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("copied {} of {} bytes", copied, len),
+        ));
+    }
+
+    Ok(CopyReport {
+        method,
+        bytes: len,
+        elapsed,
+        syscalls,
+        shared: sparse::is_shared(&output)?,
+    })
+}
+
+// Run a transfer syscall until it moves something or fails for real.
+// 0 means end of input.
+fn retry(mut f: impl FnMut() -> isize, calls: &mut u64) -> io::Result<usize> {
+    loop {
+        *calls += 1;
+        let n = f();
+        if n >= 0 {
+            return Ok(n as usize);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+fn read_write(input: &File, output: &File, chunk: usize) -> io::Result<u64> {
+    let mut buf = vec![0u8; chunk];
+    let mut calls = 0;
+    let mut offset = 0;
+    let mut input = input;
+    loop {
+        calls += 1;
+        let n = match input.read(&mut buf) {
+            Ok(0) => return Ok(calls),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let mut done = 0;
+        while done < n {
+            calls += 1;
+            match output.write_at(&buf[done..n], offset) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(w) => {
+                    done += w;
+                    offset += w as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn sendfile(input: &File, output: &File, len: u64, chunk: usize) -> io::Result<u64> {
+    let (inp, out) = (input.as_raw_fd(), output.as_raw_fd());
+    let mut calls = 0;
+    let mut offset: libc::off_t = 0;
+    while (offset as u64) < len {
+        let want = (len - offset as u64).min(chunk as u64) as usize;
+        // sendfile advances `offset` itself
+        let n = retry(
+            || unsafe { libc::sendfile(out, inp, &mut offset, want) },
+            &mut calls,
+        )?;
+        if n == 0 {
+            break; // file shrank underneath us
+        }
+    }
+    Ok(calls)
+}
+
+fn splice(input: &File, output: &File, len: u64, chunk: usize) -> io::Result<u64> {
+    let (pipe_read, pipe_write) = nix::unistd::pipe()?;
+    let mut calls = 1;
+    // a bigger pipe means fewer round trips; fine if the kernel says no
+    let pipe_size =
+        unsafe { libc::fcntl(pipe_write.as_raw_fd(), libc::F_SETPIPE_SZ, chunk as i32) };
+    calls += 1;
+    let chunk = if pipe_size > 0 {
+        chunk.min(pipe_size as usize)
+    } else {
+        chunk.min(64 << 10)
+    };
+
+    let (inp, out) = (input.as_raw_fd(), output.as_raw_fd());
+    let mut in_off: i64 = 0;
+    let mut out_off: i64 = 0;
+    while (in_off as u64) < len {
+        let want = (len - in_off as u64).min(chunk as u64) as usize;
+        let mut buffered = retry(
+            || splice_raw(inp, Some(&mut in_off), pipe_write.as_raw_fd(), None, want),
+            &mut calls,
+        )?;
+        if buffered == 0 {
+            break;
+        }
+        while buffered > 0 {
+            let n = retry(
+                || {
+                    splice_raw(
+                        pipe_read.as_raw_fd(),
+                        None,
+                        out,
+                        Some(&mut out_off),
+                        buffered,
+                    )
+                },
+                &mut calls,
+            )?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            buffered -= n;
+        }
+    }
+    Ok(calls)
+}
+
+// Offsets are only allowed on the file side of a splice, never on the pipe.
+fn splice_raw(
+    fd_in: RawFd,
+    off_in: Option<&mut i64>,
+    fd_out: RawFd,
+    off_out: Option<&mut i64>,
+    len: usize,
+) -> isize {
+    let off_in = off_in.map_or(std::ptr::null_mut(), |o| o as *mut i64);
+    let off_out = off_out.map_or(std::ptr::null_mut(), |o| o as *mut i64);
+    unsafe { libc::splice(fd_in, off_in, fd_out, off_out, len, libc::SPLICE_F_MOVE) }
+}
+
+fn copy_file_range(input: &File, output: &File, len: u64, chunk: usize) -> io::Result<u64> {
+    let (inp, out) = (input.as_raw_fd(), output.as_raw_fd());
+    let mut calls = 0;
+    let mut in_off: i64 = 0;
+    let mut out_off: i64 = 0;
+    while (in_off as u64) < len {
+        let want = (len - in_off as u64).min(chunk as u64) as usize;
+        let n = retry(
+            || unsafe { libc::copy_file_range(inp, &mut in_off, out, &mut out_off, want, 0) },
+            &mut calls,
+        )?;
+        if n == 0 {
+            break;
+        }
+    }
+    Ok(calls)
+}
+
+/// Compare two files byte for byte. Returns the offset of the first
+/// difference (or the shorter length if one is a prefix of the other).
+pub fn verify<P: AsRef<Path>, Q: AsRef<Path>>(a: P, b: Q) -> io::Result<Option<u64>> {
+    let (a, b) = (File::open(a)?, File::open(b)?);
+    let (mut buf_a, mut buf_b) = (vec![0u8; 1 << 20], vec![0u8; 1 << 20]);
+    let mut offset = 0;
+    loop {
+        let n = read_full(&a, &mut buf_a, offset)?;
+        let m = read_full(&b, &mut buf_b, offset)?;
+        if let Some(i) = buf_a[..n.min(m)]
+            .iter()
+            .zip(&buf_b[..m.min(n)])
+            .position(|(x, y)| x != y)
+        {
+            return Ok(Some(offset + i as u64));
+        }
+        if n != m {
+            return Ok(Some(offset + n.min(m) as u64));
+        }
+        if n == 0 {
+            return Ok(None);
+        }
+        offset += n as u64;
+    }
+}
+
+// pread until `buf` is full or EOF.
+fn read_full(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let mut done = 0;
+    while done < buf.len() {
+        match file.read_at(&mut buf[done..], offset + done as u64) {
+            Ok(0) => break,
+            Ok(n) => done += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(done)
+}
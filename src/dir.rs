@@ -0,0 +1,210 @@
+// An open directory and the *at(2) calls relative to it. Going through a
+// directory fd skips resolving the directory's own path on every call, so
+// metadata benchmarks time the operation on the entry and not the lookup of
+// its parents.
+//
+// Names are relative to the directory and may contain '/'.
+
+use std::{
+    ffi::CString,
+    fs::File,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    path::Path,
+};
+
+fn cstr(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("name contains a NUL byte: {:?}", name),
+        )
+    })
+}
+
+fn check(rc: i32) -> io::Result<()> {
+    if rc == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub struct Dir {
+    fd: OwnedFd,
+}
+
+impl AsRawFd for Dir {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl Dir {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let c = cstr(&path.to_string_lossy())?;
+        let fd = unsafe {
+            libc::open(
+                c.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// The subdirectory `name`, opened relative to this one.
+    pub fn open_dir(&self, name: &str) -> io::Result<Self> {
+        let fd = self.openat(name, libc::O_RDONLY | libc::O_DIRECTORY, 0)?;
+        Ok(Self { fd })
+    }
+
+    fn openat(&self, name: &str, flags: i32, mode: libc::mode_t) -> io::Result<OwnedFd> {
+        let c = cstr(name)?;
+        let fd = unsafe {
+            libc::openat(
+                self.as_raw_fd(),
+                c.as_ptr(),
+                flags | libc::O_CLOEXEC,
+                mode as libc::c_uint,
+            )
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// openat(2) an existing file with `flags` (O_RDONLY, O_WRONLY...).
+    pub fn open_file(&self, name: &str, flags: i32) -> io::Result<File> {
+        Ok(File::from(self.openat(name, flags, 0)?))
+    }
+
+    /// Create `name`, failing if it exists.
+    pub fn create(&self, name: &str) -> io::Result<File> {
+        let fd = self.openat(name, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL, 0o644)?;
+        Ok(File::from(fd))
+    }
+
+    /// fstatat(2), not following a final symlink.
+    pub fn stat(&self, name: &str) -> io::Result<libc::stat> {
+        let c = cstr(name)?;
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        check(unsafe {
+            libc::fstatat(
+                self.as_raw_fd(),
+                c.as_ptr(),
+                &mut st,
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        })?;
+        Ok(st)
+    }
+
+    pub fn unlink(&self, name: &str) -> io::Result<()> {
+        let c = cstr(name)?;
+        check(unsafe { libc::unlinkat(self.as_raw_fd(), c.as_ptr(), 0) })
+    }
+
+    pub fn mkdir(&self, name: &str) -> io::Result<()> {
+        let c = cstr(name)?;
+        check(unsafe { libc::mkdirat(self.as_raw_fd(), c.as_ptr(), 0o755) })
+    }
+
+    pub fn rmdir(&self, name: &str) -> io::Result<()> {
+        let c = cstr(name)?;
+        check(unsafe { libc::unlinkat(self.as_raw_fd(), c.as_ptr(), libc::AT_REMOVEDIR) })
+    }
+
+    /// renameat(2) `from` here to `to` in `to_dir` (which may be this one).
+    pub fn rename(&self, from: &str, to_dir: &Dir, to: &str) -> io::Result<()> {
+        let (from, to) = (cstr(from)?, cstr(to)?);
+        check(unsafe {
+            libc::renameat(
+                self.as_raw_fd(),
+                from.as_ptr(),
+                to_dir.as_raw_fd(),
+                to.as_ptr(),
+            )
+        })
+    }
+
+    /// linkat(2): a hard link `to` in `to_dir` to `from` here.
+    pub fn link(&self, from: &str, to_dir: &Dir, to: &str) -> io::Result<()> {
+        let (from, to) = (cstr(from)?, cstr(to)?);
+        check(unsafe {
+            libc::linkat(
+                self.as_raw_fd(),
+                from.as_ptr(),
+                to_dir.as_raw_fd(),
+                to.as_ptr(),
+                0,
+            )
+        })
+    }
+
+    /// symlinkat(2): `name` here pointing at `target`.
+    pub fn symlink(&self, target: &str, name: &str) -> io::Result<()> {
+        let (target, name) = (cstr(target)?, cstr(name)?);
+        check(unsafe { libc::symlinkat(target.as_ptr(), self.as_raw_fd(), name.as_ptr()) })
+    }
+
+    /// utimensat(2) setting both times to now.
+    pub fn touch(&self, name: &str) -> io::Result<()> {
+        let c = cstr(name)?;
+        let now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: libc::UTIME_NOW,
+        };
+        let times = [now, now];
+        check(unsafe { libc::utimensat(self.as_raw_fd(), c.as_ptr(), times.as_ptr(), 0) })
+    }
+
+    /// fchmodat(2).
+    pub fn chmod(&self, name: &str, mode: libc::mode_t) -> io::Result<()> {
+        let c = cstr(name)?;
+        check(unsafe { libc::fchmodat(self.as_raw_fd(), c.as_ptr(), mode, 0) })
+    }
+
+    /// Read the whole directory from the start and count its entries, not
+    /// counting "." and "..".
+    pub fn count_entries(&self) -> io::Result<usize> {
+        // fdopendir takes ownership of the fd it's given, so give it a copy
+        let fd = unsafe { libc::dup(self.as_raw_fd()) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let dir = unsafe { libc::fdopendir(fd) };
+        if dir.is_null() {
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+        // the copy shares its offset with our fd: start from the top
+        unsafe { libc::rewinddir(dir) };
+
+        let mut count = 0;
+        let result = loop {
+            // readdir only reports errors through errno
+            unsafe { *libc::__errno_location() = 0 };
+            let entry = unsafe { libc::readdir(dir) };
+            if entry.is_null() {
+                let err = io::Error::last_os_error();
+                break match err.raw_os_error() {
+                    Some(0) | None => Ok(count),
+                    _ => Err(err),
+                };
+            }
+            let name = unsafe { std::ffi::CStr::from_ptr((*entry).d_name.as_ptr()) };
+            if name.to_bytes() != b"." && name.to_bytes() != b".." {
+                count += 1;
+            }
+        };
+        unsafe { libc::closedir(dir) };
+        result
+    }
+}
@@ -0,0 +1,325 @@
+// O_DIRECT file writer/reader.
+//
+// With O_DIRECT the kernel DMAs straight from/to our memory, so the buffer
+// address, the transfer length and the file offset all have to be multiples
+// of the device's logical block size. DirectFile checks that up front and
+// returns a typed error instead of letting the kernel answer EINVAL.
+
+use std::{
+    cell::Cell,
+    error::Error,
+    fmt,
+    fs::{File, OpenOptions},
+    io,
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::Path,
+};
+
+use libc::O_DIRECT;
+
+use crate::{aligned_buf::AlignedBuf, instrument, probe};
+
+// Size of the bounce buffer used by write_all/read_all.
+const CHUNK: usize = 1 << 20;
+
+#[derive(Debug)]
+pub enum DirectError {
+    /// An offset, length or buffer address is not a multiple of the alignment.
+    Misaligned {
+        what: &'static str,
+        value: u64,
+        align: usize,
+    },
+    /// The kernel kept returning less than one aligned block.
+    ShortWrite {
+        offset: u64,
+        written: usize,
+    },
+    /// Read-back content differs from what was written.
+    Mismatch {
+        offset: u64,
+    },
+    /// The filesystem refuses O_DIRECT; the message says why.
+    Unsupported(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for DirectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DirectError::Misaligned { what, value, align } => {
+                write!(
+                    f,
+                    "{} {} is not a multiple of the {}-byte alignment",
+                    what, value, align
+                )
+            }
+            DirectError::ShortWrite { offset, written } => {
+                write!(
+                    f,
+                    "short write of {} bytes at offset {} made no progress",
+                    written, offset
+                )
+            }
+            DirectError::Mismatch { offset } => {
+                write!(f, "read-back data differs at offset {}", offset)
+            }
+            DirectError::Unsupported(why) => write!(f, "O_DIRECT not supported: {}", why),
+            DirectError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for DirectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DirectError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DirectError {
+    fn from(e: io::Error) -> Self {
+        DirectError::Io(e)
+    }
+}
+
+impl From<DirectError> for io::Error {
+    fn from(e: DirectError) -> Self {
+        match e {
+            DirectError::Io(e) => e,
+            DirectError::Misaligned { .. } => io::Error::new(io::ErrorKind::InvalidInput, e),
+            DirectError::ShortWrite { .. } => io::Error::new(io::ErrorKind::WriteZero, e),
+            DirectError::Mismatch { .. } => io::Error::new(io::ErrorKind::InvalidData, e),
+            DirectError::Unsupported(_) => io::Error::new(io::ErrorKind::Unsupported, e),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, DirectError>;
+
+pub struct DirectFile {
+    file: File,
+    align: usize,
+    syscalls: Cell<u64>,
+}
+
+impl DirectFile {
+    /// Create (or truncate) `path` for O_DIRECT reading and writing.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::create_with_flags(path, 0)
+    }
+
+    /// Like [`DirectFile::create`], adding `flags` (e.g. O_DSYNC) to the open.
+    pub fn create_with_flags<P: AsRef<Path>>(path: P, flags: i32) -> Result<Self> {
+        let path = path.as_ref();
+        let mut opts = OpenOptions::new();
+        opts.read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o644)
+            .custom_flags(O_DIRECT | flags);
+        let file =
+            instrument::timed("open", || opts.open(path)).map_err(|e| open_error(path, e))?;
+        Ok(Self::from_file(file))
+    }
+
+    /// Open an existing file read-only with O_DIRECT.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut opts = OpenOptions::new();
+        opts.read(true).custom_flags(O_DIRECT);
+        let file =
+            instrument::timed("open", || opts.open(path)).map_err(|e| open_error(path, e))?;
+        Ok(Self::from_file(file))
+    }
+
+    /// Open an existing file for O_DIRECT reading and writing, keeping its contents.
+    pub fn open_rw<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut opts = OpenOptions::new();
+        opts.read(true).write(true).custom_flags(O_DIRECT);
+        let file =
+            instrument::timed("open", || opts.open(path)).map_err(|e| open_error(path, e))?;
+        Ok(Self::from_file(file))
+    }
+
+    /// Wrap a file that was already opened with O_DIRECT.
+    pub fn from_file(file: File) -> Self {
+        let align = probe::alignment(&file);
+        Self {
+            file,
+            align,
+            syscalls: Cell::new(0),
+        }
+    }
+
+    pub fn alignment(&self) -> usize {
+        self.align
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Number of pread/pwrite/ftruncate calls issued so far.
+    pub fn syscalls(&self) -> u64 {
+        self.syscalls.get()
+    }
+
+    /// An aligned buffer of `len` bytes suitable for this file.
+    pub fn buffer(&self, len: usize) -> Result<AlignedBuf> {
+        Ok(AlignedBuf::new(len, self.align)?)
+    }
+
+    /// Fail unless every transfer of `len` bytes can be aligned; block sizes
+    /// aren't rounded up behind the caller's back.
+    pub fn check_block_size(&self, len: usize) -> Result<()> {
+        self.check("block size", len as u64)
+    }
+
+    fn check(&self, what: &'static str, value: u64) -> Result<()> {
+        if value.is_multiple_of(self.align as u64) {
+            Ok(())
+        } else {
+            Err(DirectError::Misaligned {
+                what,
+                value,
+                align: self.align,
+            })
+        }
+    }
+
+    fn check_io(&self, ptr: *const u8, len: usize, offset: u64) -> Result<()> {
+        self.check("offset", offset)?;
+        self.check("length", len as u64)?;
+        self.check("buffer address", ptr as u64)
+    }
+
+    /// Write all of `buf` at `offset`, retrying short writes.
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> Result<()> {
+        self.check_io(buf.as_ptr(), buf.len(), offset)?;
+
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            self.syscalls.set(self.syscalls.get() + 1);
+            let n = match instrument::timed("pwrite", || self.file.write_at(&buf[done..], pos)) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            // A partial block can't be continued from an unaligned offset,
+            // so rewind to the last whole block and write the rest again.
+            let next = (done + n) / self.align * self.align;
+            if next == done {
+                return Err(DirectError::ShortWrite {
+                    offset: pos,
+                    written: n,
+                });
+            }
+            done = next;
+        }
+        Ok(())
+    }
+
+    /// Fill `buf` from `offset`, stopping early only at end of file.
+    /// Returns the number of bytes read.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.check_io(buf.as_ptr(), buf.len(), offset)?;
+
+        let mut done = 0;
+        while done < buf.len() {
+            self.syscalls.set(self.syscalls.get() + 1);
+            match instrument::timed("pread", || {
+                self.file.read_at(&mut buf[done..], offset + done as u64)
+            }) {
+                Ok(0) => break,
+                Ok(n) => {
+                    done += n;
+                    // Only the block holding end-of-file comes back partial.
+                    if !n.is_multiple_of(self.align) {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(done)
+    }
+
+    /// Replace the file's contents with `data`, whatever its length.
+    ///
+    /// Data goes through an aligned bounce buffer; the tail block is padded
+    /// with zeroes and the file is then truncated back to `data.len()`.
+    pub fn write_all(&self, data: &[u8]) -> Result<()> {
+        let mut buf = self.buffer(CHUNK.next_multiple_of(self.align))?;
+
+        let mut offset = 0;
+        for piece in data.chunks(buf.len()) {
+            let padded = piece.len().next_multiple_of(self.align);
+            buf[..piece.len()].copy_from_slice(piece);
+            buf[piece.len()..padded].fill(0);
+            self.write_at(&buf[..padded], offset)?;
+            offset += piece.len() as u64;
+        }
+
+        self.set_len(data.len() as u64)
+    }
+
+    /// Truncate or extend the file. Needed after padding the tail block.
+    pub fn set_len(&self, len: u64) -> Result<()> {
+        self.syscalls.set(self.syscalls.get() + 1);
+        Ok(instrument::timed("ftruncate", || self.file.set_len(len))?)
+    }
+
+    /// Read the whole file through an aligned bounce buffer.
+    pub fn read_all(&self) -> Result<Vec<u8>> {
+        let size = self.file.metadata()?.len();
+        let mut buf = self.buffer(CHUNK.next_multiple_of(self.align))?;
+        let mut out = Vec::with_capacity(size as usize);
+
+        loop {
+            let n = self.read_at(&mut buf, out.len() as u64)?;
+            out.extend_from_slice(&buf[..n]);
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Read the file back and compare it with `expected`.
+    pub fn verify(&self, expected: &[u8]) -> Result<()> {
+        let actual = self.read_all()?;
+        if let Some(offset) = actual.iter().zip(expected).position(|(a, b)| a != b) {
+            return Err(DirectError::Mismatch {
+                offset: offset as u64,
+            });
+        }
+        if actual.len() != expected.len() {
+            return Err(DirectError::Mismatch {
+                offset: actual.len().min(expected.len()) as u64,
+            });
+        }
+        Ok(())
+    }
+}
+
+// EINVAL from open(O_DIRECT) means the filesystem doesn't do direct I/O;
+// ask the probe for a readable explanation.
+fn open_error(path: &Path, e: io::Error) -> DirectError {
+    if e.raw_os_error() != Some(libc::EINVAL) {
+        return e.into();
+    }
+    let why = match probe::probe(path) {
+        Ok(p) => p
+            .unsupported
+            .unwrap_or_else(|| format!("{} returned EINVAL for O_DIRECT", p.fs_type)),
+        Err(_) => "open returned EINVAL".to_string(),
+    };
+    DirectError::Unsupported(why)
+}
@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+// Log-linear buckets: values below 2^SUB_BITS are exact, above that every
+// power of two is split into 2^SUB_BITS buckets (~3% relative error).
+// Keeps percentiles cheap even for the 100M-write runs.
+const SUB_BITS: u32 = 5;
+const SUB_COUNT: usize = 1 << SUB_BITS;
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_COUNT;
+
+/// Latency histogram in nanoseconds.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    pub fn record(&mut self, nanos: u64) {
+        self.buckets[bucket_index(nanos)] += 1;
+        self.count += 1;
+        self.sum += nanos as u128;
+        self.min = self.min.min(nanos);
+        self.max = self.max.max(nanos);
+    }
+
+    pub fn record_duration(&mut self, d: Duration) {
+        self.record(d.as_nanos().min(u64::MAX as u128) as u64);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (a, b) in self.buckets.iter_mut().zip(&other.buckets) {
+            *a += b;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Append the histogram to `out` as plain integers, e.g. to send it
+    /// back from a forked child. [`Histogram::from_words`] reads it back.
+    pub fn to_words(&self, out: &mut Vec<u64>) {
+        out.extend([
+            self.count,
+            self.sum as u64,
+            (self.sum >> 64) as u64,
+            self.min,
+            self.max,
+        ]);
+        out.extend(&self.buckets);
+    }
+
+    /// Take one histogram off the front of `words`.
+    pub fn from_words(words: &mut impl Iterator<Item = u64>) -> Option<Self> {
+        let mut h = Self::new();
+        h.count = words.next()?;
+        h.sum = words.next()? as u128 | (words.next()? as u128) << 64;
+        h.min = words.next()?;
+        h.max = words.next()?;
+        for b in &mut h.buckets {
+            *b = words.next()?;
+        }
+        Some(h)
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> u128 {
+        self.sum
+    }
+
+    pub fn min(&self) -> u64 {
+        if self.count == 0 { 0 } else { self.min }
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.count as f64
+        }
+    }
+
+    /// Value at percentile `p` (0.0..=100.0), reported as the bucket's lower bound.
+    pub fn percentile(&self, p: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((p / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (idx, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return bucket_value(idx).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+}
+
+fn bucket_index(v: u64) -> usize {
+    if v < SUB_COUNT as u64 {
+        return v as usize;
+    }
+    let exp = 63 - v.leading_zeros();
+    let shift = exp - SUB_BITS;
+    let mantissa = ((v >> shift) as usize) & (SUB_COUNT - 1);
+    (shift as usize + 1) * SUB_COUNT + mantissa
+}
+
+fn bucket_value(idx: usize) -> u64 {
+    if idx < SUB_COUNT {
+        return idx as u64;
+    }
+    let shift = idx / SUB_COUNT - 1;
+    let mantissa = (idx % SUB_COUNT) as u64;
+    (SUB_COUNT as u64 + mantissa) << shift
+}
+
+/// Human readable nanoseconds: `850ns`, `12.4µs`, `3.10ms`, `1.25s`.
+pub fn fmt_nanos(nanos: u64) -> String {
+    let n = nanos as f64;
+    if nanos < 1_000 {
+        format!("{}ns", nanos)
+    } else if nanos < 1_000_000 {
+        format!("{:.1}µs", n / 1e3)
+    } else if nanos < 1_000_000_000 {
+        format!("{:.2}ms", n / 1e6)
+    } else {
+        format!("{:.2}s", n / 1e9)
+    }
+}
@@ -0,0 +1,167 @@
+// Poor man's strace: count the syscalls our binaries make and how long each took.
+//
+// Off by default so library code can call `timed` unconditionally. A binary
+// turns it on with `enable()` and gets a per-syscall summary on stderr when
+// the returned guard is dropped at the end of main.
+
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+use crate::{
+    histogram::{Histogram, fmt_nanos},
+    report::Record,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STATS: Mutex<BTreeMap<&'static str, Histogram>> = Mutex::new(BTreeMap::new());
+
+/// Prints the summary when dropped.
+pub struct SummaryGuard(());
+
+impl Drop for SummaryGuard {
+    fn drop(&mut self) {
+        print_summary();
+    }
+}
+
+/// Start recording. Keep the guard alive for as long as you want to measure.
+#[must_use = "the summary is printed when the guard is dropped"]
+pub fn enable() -> SummaryGuard {
+    ENABLED.store(true, Ordering::Relaxed);
+    SummaryGuard(())
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Run one syscall wrapper `f`, recording it under `name` if enabled.
+pub fn timed<T, E>(name: &'static str, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    if !is_enabled() {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let nanos = start.elapsed().as_nanos() as u64;
+    STATS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(name)
+        .or_default()
+        .record(nanos);
+    result
+}
+
+/// Calls recorded so far for `name`.
+pub fn count(name: &str) -> u64 {
+    STATS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(name)
+        .map_or(0, Histogram::count)
+}
+
+/// Forget everything recorded so far, e.g. between repetitions of a benchmark.
+pub fn reset() {
+    STATS.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+pub fn print_summary() {
+    let stats = STATS.lock().unwrap_or_else(|e| e.into_inner());
+    if stats.is_empty() {
+        return;
+    }
+    eprintln!(
+        "\n{:<10} {:>12} {:>10} {:>9} {:>9} {:>9} {:>9}",
+        "syscall", "calls", "total", "mean", "p50", "p99", "max"
+    );
+    for (name, h) in stats.iter() {
+        let total = h.sum().min(u64::MAX as u128) as u64;
+        eprintln!(
+            "{:<10} {:>12} {:>10} {:>9} {:>9} {:>9} {:>9}",
+            name,
+            h.count(),
+            fmt_nanos(total),
+            fmt_nanos(h.mean() as u64),
+            fmt_nanos(h.percentile(50.0)),
+            fmt_nanos(h.percentile(99.0)),
+            fmt_nanos(h.max()),
+        );
+    }
+}
+
+/// Add what was recorded so far to `record`, as `<syscall>_count`,
+/// `<syscall>_p50_ns` and so on.
+pub fn add_to(mut record: Record) -> Record {
+    let stats = STATS.lock().unwrap_or_else(|e| e.into_inner());
+    for (name, h) in stats.iter() {
+        record = record.latency(name, h);
+    }
+    record
+}
+
+/// `OpenOptions::open`, recorded as "open".
+pub fn open<P: AsRef<Path>>(opts: &OpenOptions, path: P) -> io::Result<Instrumented<File>> {
+    timed("open", || opts.open(path)).map(Instrumented::new)
+}
+
+/// Wraps a reader/writer so every read, write and seek is recorded.
+/// Dropping it records the close.
+pub struct Instrumented<T> {
+    inner: Option<T>,
+}
+
+impl<T> Instrumented<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner: Some(inner) }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        self.inner.as_ref().expect("used after drop")
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.as_mut().expect("used after drop")
+    }
+}
+
+impl<T> Drop for Instrumented<T> {
+    fn drop(&mut self) {
+        let inner = self.inner.take();
+        let _ = timed("close", || {
+            drop(inner);
+            Ok::<_, ()>(())
+        });
+    }
+}
+
+impl<T: Read> Read for Instrumented<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        timed("read", || self.get_mut().read(buf))
+    }
+}
+
+impl<T: Write> Write for Instrumented<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        timed("write", || self.get_mut().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
+
+impl<T: Seek> Seek for Instrumented<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        timed("lseek", || self.get_mut().seek(pos))
+    }
+}
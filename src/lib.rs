@@ -0,0 +1,20 @@
+// Shared code for the experiments in src/bin.
+// Each binary stays a small demo; anything reused across them lives here.
+
+pub mod aligned_buf;
+pub mod bench;
+pub mod cache;
+pub mod cli;
+pub mod compare;
+pub mod copy;
+pub mod dir;
+pub mod direct;
+pub mod histogram;
+pub mod instrument;
+pub mod lock;
+pub mod mmap;
+pub mod names;
+pub mod probe;
+pub mod report;
+pub mod runner;
+pub mod sparse;
@@ -0,0 +1,567 @@
+// The three kinds of advisory file lock Linux offers, behind one interface:
+//
+//   flock  flock(2): whole file, owned by the open file description
+//   posix  fcntl(2) F_SETLK/F_SETLKW: byte ranges, owned by the process
+//   ofd    fcntl(2) F_OFD_SETLK/F_OFD_SETLKW: byte ranges, owned by the open
+//          file description (Linux 3.15+)
+//
+// Who owns a lock decides what forks, closes and threads do to it; `cases`
+// checks each of those rules on the running kernel.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    sys::wait::{WaitStatus, waitpid},
+    unistd::{ForkResult, fork, pipe},
+};
+
+use crate::bench::lookup;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Flock,
+    Posix,
+    Ofd,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 3] = [Kind::Flock, Kind::Posix, Kind::Ofd];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Flock => "flock",
+            Kind::Posix => "posix",
+            Kind::Ofd => "ofd",
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lookup(&Kind::ALL, s, |k| k.name(), "lock kind")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Shared,
+    Exclusive,
+}
+
+/// Bytes `start..start + len`; a `len` of 0 runs to the end of the file,
+/// however far it grows. flock ignores ranges.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Range {
+    pub start: u64,
+    pub len: u64,
+}
+
+impl Range {
+    pub const WHOLE: Range = Range { start: 0, len: 0 };
+
+    pub fn new(start: u64, len: u64) -> Self {
+        Self { start, len }
+    }
+}
+
+fn flock_struct(kind: i32, range: Range) -> libc::flock {
+    // l_pid must be 0 for OFD locks
+    let mut fl: libc::flock = unsafe { std::mem::zeroed() };
+    fl.l_type = kind as i16;
+    fl.l_whence = libc::SEEK_SET as i16;
+    fl.l_start = range.start as i64;
+    fl.l_len = range.len as i64;
+    fl
+}
+
+fn retry(mut f: impl FnMut() -> i32) -> io::Result<()> {
+    loop {
+        if f() != -1 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Take a lock on `range` of `file`. With `wait` the call blocks until the
+/// lock is granted; without it, Ok(false) means a conflicting lock is held.
+/// A wait that would deadlock fails with EDEADLK (see [`is_deadlock`]).
+pub fn lock(file: &File, kind: Kind, mode: Mode, range: Range, wait: bool) -> io::Result<bool> {
+    let fd = file.as_raw_fd();
+    let result = match kind {
+        Kind::Flock => {
+            let op = match mode {
+                Mode::Shared => libc::LOCK_SH,
+                Mode::Exclusive => libc::LOCK_EX,
+            };
+            let op = if wait { op } else { op | libc::LOCK_NB };
+            retry(|| unsafe { libc::flock(fd, op) })
+        }
+        Kind::Posix | Kind::Ofd => {
+            let fl = flock_struct(
+                match mode {
+                    Mode::Shared => libc::F_RDLCK,
+                    Mode::Exclusive => libc::F_WRLCK,
+                },
+                range,
+            );
+            let cmd = match (kind, wait) {
+                (Kind::Posix, false) => libc::F_SETLK,
+                (Kind::Posix, true) => libc::F_SETLKW,
+                (_, false) => libc::F_OFD_SETLK,
+                (_, true) => libc::F_OFD_SETLKW,
+            };
+            retry(|| unsafe { libc::fcntl(fd, cmd, &fl) })
+        }
+    };
+    match result {
+        Ok(()) => Ok(true),
+        // EACCES is what POSIX allows F_SETLK to return instead of EAGAIN
+        Err(e) if matches!(e.raw_os_error(), Some(libc::EAGAIN | libc::EACCES)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn unlock(file: &File, kind: Kind, range: Range) -> io::Result<()> {
+    let fd = file.as_raw_fd();
+    match kind {
+        Kind::Flock => retry(|| unsafe { libc::flock(fd, libc::LOCK_UN) }),
+        Kind::Posix | Kind::Ofd => {
+            let fl = flock_struct(libc::F_UNLCK, range);
+            let cmd = if kind == Kind::Posix {
+                libc::F_SETLK
+            } else {
+                libc::F_OFD_SETLK
+            };
+            retry(|| unsafe { libc::fcntl(fd, cmd, &fl) })
+        }
+    }
+}
+
+pub fn is_deadlock(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EDEADLK)
+}
+
+/// A lock that would conflict, as F_GETLK describes it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Holder {
+    /// Process holding it; -1 for OFD locks, which no process owns.
+    pub pid: i32,
+    pub range: Range,
+    pub exclusive: bool,
+}
+
+/// Who holds a lock that would stop us taking `mode` on `range`, if anyone.
+/// flock has no way to ask.
+pub fn holder(file: &File, kind: Kind, mode: Mode, range: Range) -> io::Result<Option<Holder>> {
+    let cmd = match kind {
+        Kind::Flock => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "flock locks can't be queried",
+            ));
+        }
+        Kind::Posix => libc::F_GETLK,
+        Kind::Ofd => libc::F_OFD_GETLK,
+    };
+    let mut fl = flock_struct(
+        match mode {
+            Mode::Shared => libc::F_RDLCK,
+            Mode::Exclusive => libc::F_WRLCK,
+        },
+        range,
+    );
+    retry(|| unsafe { libc::fcntl(file.as_raw_fd(), cmd, &mut fl) })?;
+    if fl.l_type == libc::F_UNLCK as i16 {
+        return Ok(None);
+    }
+    Ok(Some(Holder {
+        pid: fl.l_pid,
+        range: Range::new(fl.l_start as u64, fl.l_len as u64),
+        exclusive: fl.l_type == libc::F_WRLCK as i16,
+    }))
+}
+
+// Run `f` in a forked child and hand back its answer. The child exits
+// without running anything else of ours.
+fn in_child(f: impl FnOnce() -> io::Result<bool>) -> io::Result<bool> {
+    match unsafe { fork() }? {
+        ForkResult::Child => {
+            let code = match f() {
+                Ok(true) => 0,
+                Ok(false) => 1,
+                Err(e) => {
+                    eprintln!("child: {}", e);
+                    2
+                }
+            };
+            unsafe { libc::_exit(code) };
+        }
+        ForkResult::Parent { child } => match waitpid(child, None)? {
+            WaitStatus::Exited(_, 0) => Ok(true),
+            WaitStatus::Exited(_, 1) => Ok(false),
+            status => Err(io::Error::other(format!("child failed: {:?}", status))),
+        },
+    }
+}
+
+/// One rule about lock ownership, checked on the running kernel.
+pub struct Case {
+    pub name: &'static str,
+    /// What the rule says should happen.
+    pub claim: &'static str,
+    pub expected: &'static str,
+    run: fn(&Path) -> io::Result<&'static str>,
+}
+
+impl Case {
+    /// Run the case against a scratch file at `path` (created and removed
+    /// here) and return what actually happened.
+    pub fn run(&self, path: &Path) -> io::Result<&'static str> {
+        File::create(path)?.set_len(4096)?;
+        let result = (self.run)(path);
+        let _ = std::fs::remove_file(path);
+        result
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).open(path)
+}
+
+fn granted(ok: bool) -> &'static str {
+    if ok { "granted" } else { "blocked" }
+}
+
+const X: Mode = Mode::Exclusive;
+const WHOLE: Range = Range::WHOLE;
+
+/// All the cases, in the order they're best read in.
+pub fn cases() -> Vec<Case> {
+    vec![
+        Case {
+            name: "flock-between-opens",
+            claim: "flock locks of two open() calls in one process conflict",
+            expected: "blocked",
+            run: |path| {
+                let (a, b) = (open(path)?, open(path)?);
+                lock(&a, Kind::Flock, X, WHOLE, false)?;
+                Ok(granted(lock(&b, Kind::Flock, X, WHOLE, false)?))
+            },
+        },
+        Case {
+            name: "posix-same-process",
+            claim: "POSIX locks never conflict within one process",
+            expected: "granted",
+            run: |path| {
+                let (a, b) = (open(path)?, open(path)?);
+                lock(&a, Kind::Posix, X, WHOLE, false)?;
+                Ok(granted(lock(&b, Kind::Posix, X, WHOLE, false)?))
+            },
+        },
+        Case {
+            name: "ofd-between-opens",
+            claim: "OFD locks of two open() calls in one process conflict",
+            expected: "blocked",
+            run: |path| {
+                let (a, b) = (open(path)?, open(path)?);
+                lock(&a, Kind::Ofd, X, WHOLE, false)?;
+                Ok(granted(lock(&b, Kind::Ofd, X, WHOLE, false)?))
+            },
+        },
+        Case {
+            name: "posix-threads",
+            claim: "POSIX locks don't exclude threads of the same process",
+            expected: "granted",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Posix, X, WHOLE, false)?;
+                let other = thread::scope(|s| {
+                    s.spawn(|| lock(&open(path)?, Kind::Posix, X, WHOLE, false))
+                        .join()
+                        .unwrap_or_else(|_| Err(io::Error::other("thread panicked")))
+                })?;
+                Ok(granted(other))
+            },
+        },
+        Case {
+            name: "posix-close-any",
+            claim: "closing any descriptor of the file drops the process's POSIX locks",
+            expected: "released",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Posix, X, WHOLE, false)?;
+                drop(open(path)?);
+                let free = in_child(|| lock(&open(path)?, Kind::Posix, X, WHOLE, false))?;
+                Ok(if free { "released" } else { "held" })
+            },
+        },
+        Case {
+            name: "ofd-close-other",
+            claim: "closing another descriptor leaves an OFD lock alone",
+            expected: "held",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Ofd, X, WHOLE, false)?;
+                drop(open(path)?);
+                let free = in_child(|| lock(&open(path)?, Kind::Ofd, X, WHOLE, false))?;
+                Ok(if free { "released" } else { "held" })
+            },
+        },
+        Case {
+            name: "flock-close-other",
+            claim: "closing another descriptor leaves a flock lock alone",
+            expected: "held",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Flock, X, WHOLE, false)?;
+                drop(open(path)?);
+                let free = in_child(|| lock(&open(path)?, Kind::Flock, X, WHOLE, false))?;
+                Ok(if free { "released" } else { "held" })
+            },
+        },
+        Case {
+            name: "flock-fork",
+            claim: "a forked child shares the parent's flock lock through the inherited descriptor",
+            expected: "granted",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Flock, X, WHOLE, false)?;
+                Ok(granted(in_child(|| {
+                    lock(&a, Kind::Flock, X, WHOLE, false)
+                })?))
+            },
+        },
+        Case {
+            name: "posix-fork",
+            claim: "POSIX locks aren't inherited: the child conflicts with its parent",
+            expected: "blocked",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Posix, X, WHOLE, false)?;
+                Ok(granted(in_child(|| {
+                    lock(&a, Kind::Posix, X, WHOLE, false)
+                })?))
+            },
+        },
+        Case {
+            name: "ofd-fork",
+            claim: "a forked child shares the parent's OFD lock through the inherited descriptor",
+            expected: "granted",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Ofd, X, WHOLE, false)?;
+                Ok(granted(in_child(|| lock(&a, Kind::Ofd, X, WHOLE, false))?))
+            },
+        },
+        Case {
+            name: "flock-posix-independent",
+            claim: "flock and POSIX locks don't see each other (on local filesystems)",
+            expected: "granted",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Flock, X, WHOLE, false)?;
+                Ok(granted(in_child(|| {
+                    lock(&open(path)?, Kind::Posix, X, WHOLE, false)
+                })?))
+            },
+        },
+        Case {
+            name: "posix-ranges",
+            claim: "record locks only conflict where the byte ranges overlap",
+            expected: "adjacent granted, overlap blocked",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Posix, X, Range::new(0, 100), false)?;
+                let adjacent =
+                    in_child(|| lock(&open(path)?, Kind::Posix, X, Range::new(100, 100), false))?;
+                let overlap =
+                    in_child(|| lock(&open(path)?, Kind::Posix, X, Range::new(50, 100), false))?;
+                Ok(match (adjacent, overlap) {
+                    (true, false) => "adjacent granted, overlap blocked",
+                    (true, true) => "both granted",
+                    (false, false) => "both blocked",
+                    (false, true) => "adjacent blocked, overlap granted",
+                })
+            },
+        },
+        Case {
+            name: "posix-shared",
+            claim: "shared (read) locks coexist, an exclusive one doesn't",
+            expected: "shared granted, exclusive blocked",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Posix, Mode::Shared, WHOLE, false)?;
+                let shared =
+                    in_child(|| lock(&open(path)?, Kind::Posix, Mode::Shared, WHOLE, false))?;
+                let exclusive = in_child(|| lock(&open(path)?, Kind::Posix, X, WHOLE, false))?;
+                Ok(match (shared, exclusive) {
+                    (true, false) => "shared granted, exclusive blocked",
+                    (true, true) => "both granted",
+                    (false, _) => "shared blocked",
+                })
+            },
+        },
+        Case {
+            name: "posix-getlk",
+            claim: "F_GETLK names the process holding a POSIX lock",
+            expected: "holder is the parent",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Posix, X, WHOLE, false)?;
+                let parent = std::process::id() as i32;
+                let matches = in_child(|| {
+                    let h = holder(&open(path)?, Kind::Posix, X, WHOLE)?;
+                    Ok(h.is_some_and(|h| h.pid == parent && h.exclusive))
+                })?;
+                Ok(if matches {
+                    "holder is the parent"
+                } else {
+                    "holder not reported"
+                })
+            },
+        },
+        Case {
+            name: "ofd-getlk",
+            claim: "F_OFD_GETLK reports pid -1: no process owns an OFD lock",
+            expected: "pid -1",
+            run: |path| {
+                let a = open(path)?;
+                lock(&a, Kind::Ofd, X, WHOLE, false)?;
+                let h = holder(&open(path)?, Kind::Ofd, X, WHOLE)?;
+                Ok(match h {
+                    Some(h) if h.pid == -1 => "pid -1",
+                    Some(_) => "a pid",
+                    None => "no holder",
+                })
+            },
+        },
+        Case {
+            name: "setlkw-waits",
+            claim: "F_SETLKW blocks until the holder lets go",
+            expected: "waited",
+            run: |path| {
+                const HOLD: Duration = Duration::from_millis(100);
+                let a = open(path)?;
+                lock(&a, Kind::Posix, X, WHOLE, false)?;
+                // the child says when it's about to wait; we let go after HOLD
+                let (ready_read, ready_write) = pipe()?;
+                let waited = match unsafe { fork() }? {
+                    ForkResult::Child => {
+                        drop(ready_read);
+                        let f = open(path);
+                        let _ = File::from(ready_write).write_all(b"r");
+                        let start = Instant::now();
+                        let ok = f.and_then(|f| lock(&f, Kind::Posix, X, WHOLE, true));
+                        let code = if ok.is_ok() && start.elapsed() >= HOLD / 2 {
+                            0
+                        } else {
+                            1
+                        };
+                        unsafe { libc::_exit(code) };
+                    }
+                    ForkResult::Parent { child } => {
+                        drop(ready_write);
+                        File::from(ready_read).read_exact(&mut [0u8])?;
+                        thread::sleep(HOLD);
+                        unlock(&a, Kind::Posix, WHOLE)?;
+                        matches!(waitpid(child, None)?, WaitStatus::Exited(_, 0))
+                    }
+                };
+                Ok(if waited { "waited" } else { "didn't wait" })
+            },
+        },
+        Case {
+            name: "posix-deadlock",
+            claim: "the kernel refuses a POSIX lock wait that would deadlock",
+            expected: "EDEADLK",
+            run: posix_deadlock,
+        },
+    ]
+}
+
+// Parent holds byte 0 and the child byte 1; then each waits for the
+// other's. Whichever of the two waits closes the cycle gets EDEADLK, and
+// gives up its own byte so the other can finish.
+fn posix_deadlock(path: &Path) -> io::Result<&'static str> {
+    let (first, second) = (Range::new(0, 1), Range::new(1, 1));
+    let a = open(path)?;
+    lock(&a, Kind::Posix, X, first, false)?;
+
+    let (ready_read, ready_write) = pipe()?;
+    let (child, mut child_read) = match unsafe { fork() }? {
+        ForkResult::Child => {
+            drop(ready_read);
+            let code = (|| -> io::Result<i32> {
+                let b = open(path)?;
+                lock(&b, Kind::Posix, X, second, false)?;
+                File::from(ready_write).write_all(b"r")?;
+                // give the parent time to start waiting for our byte
+                thread::sleep(Duration::from_millis(50));
+                match lock(&b, Kind::Posix, X, first, true) {
+                    Ok(_) => Ok(0),
+                    Err(e) if is_deadlock(&e) => {
+                        unlock(&b, Kind::Posix, second)?;
+                        Ok(1)
+                    }
+                    Err(e) => Err(e),
+                }
+            })()
+            .unwrap_or(2);
+            unsafe { libc::_exit(code) };
+        }
+        ForkResult::Parent { child } => {
+            drop(ready_write);
+            (child, File::from(ready_read))
+        }
+    };
+    child_read.read_exact(&mut [0u8])?;
+
+    let parent_deadlocked = match lock(&a, Kind::Posix, X, second, true) {
+        Ok(_) => false,
+        Err(e) if is_deadlock(&e) => {
+            unlock(&a, Kind::Posix, first)?;
+            true
+        }
+        Err(e) => return Err(e),
+    };
+    // the child holds `second` until it exits
+    if !parent_deadlocked {
+        unlock(&a, Kind::Posix, WHOLE)?;
+    }
+    let child_deadlocked = match waitpid(child, None)? {
+        WaitStatus::Exited(_, 0) => false,
+        WaitStatus::Exited(_, 1) => true,
+        status => return Err(io::Error::other(format!("child failed: {:?}", status))),
+    };
+    Ok(match (parent_deadlocked, child_deadlocked) {
+        (false, false) => "no deadlock detected",
+        (true, true) => "both got EDEADLK",
+        _ => "EDEADLK",
+    })
+}
+
+/// Scratch file for the cases, in `dir`.
+pub fn scratch_path(dir: &Path) -> PathBuf {
+    dir.join(format!(".lock_semantics.{}", std::process::id()))
+}
@@ -0,0 +1,222 @@
+// Safe-ish wrapper around mmap(2) of a whole file.
+//
+// The mapping is unmapped on drop. As with any shared file mapping, another
+// process truncating the file underneath us gets us SIGBUS; that's the
+// caller's problem, same as in C.
+
+use std::{
+    fmt,
+    fs::File,
+    io,
+    ops::{Deref, DerefMut},
+    os::unix::io::AsRawFd,
+    ptr::NonNull,
+    slice,
+    str::FromStr,
+};
+
+/// madvise(2) hints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Advice {
+    Normal,
+    Sequential,
+    Random,
+    WillNeed,
+    DontNeed,
+    HugePage,
+}
+
+impl Advice {
+    fn raw(self) -> i32 {
+        match self {
+            Advice::Normal => libc::MADV_NORMAL,
+            Advice::Sequential => libc::MADV_SEQUENTIAL,
+            Advice::Random => libc::MADV_RANDOM,
+            Advice::WillNeed => libc::MADV_WILLNEED,
+            Advice::DontNeed => libc::MADV_DONTNEED,
+            Advice::HugePage => libc::MADV_HUGEPAGE,
+        }
+    }
+}
+
+impl fmt::Display for Advice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Advice::Normal => "normal",
+            Advice::Sequential => "sequential",
+            Advice::Random => "random",
+            Advice::WillNeed => "willneed",
+            Advice::DontNeed => "dontneed",
+            Advice::HugePage => "hugepage",
+        })
+    }
+}
+
+impl FromStr for Advice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(Advice::Normal),
+            "sequential" => Ok(Advice::Sequential),
+            "random" => Ok(Advice::Random),
+            "willneed" => Ok(Advice::WillNeed),
+            "dontneed" => Ok(Advice::DontNeed),
+            "hugepage" => Ok(Advice::HugePage),
+            _ => Err(format!(
+                "unknown advice '{}' (expected normal, sequential, random, willneed, dontneed or hugepage)",
+                s
+            )),
+        }
+    }
+}
+
+/// How to push dirty pages of a mapping back to the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Msync {
+    /// Leave it to the kernel's writeback.
+    None,
+    /// MS_ASYNC: on Linux this only marks the pages for writeback, it's nearly free.
+    #[default]
+    Async,
+    /// MS_SYNC: wait until the pages were written.
+    Sync,
+}
+
+impl fmt::Display for Msync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Msync::None => "none",
+            Msync::Async => "async",
+            Msync::Sync => "sync",
+        })
+    }
+}
+
+impl FromStr for Msync {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Msync::None),
+            "async" => Ok(Msync::Async),
+            "sync" => Ok(Msync::Sync),
+            _ => Err(format!(
+                "unknown msync mode '{}' (expected none, async or sync)",
+                s
+            )),
+        }
+    }
+}
+
+/// A MAP_SHARED mapping of the first `len` bytes of a file.
+pub struct Mapping {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// Like a &mut [u8] into the file: exclusively owned by whoever holds the Mapping.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// Map `len` bytes of `file` read-only. The file must be at least that long.
+    pub fn read_only(file: &File, len: usize) -> io::Result<Self> {
+        Self::map(file, len, libc::PROT_READ)
+    }
+
+    /// Map `len` bytes of `file` for reading and writing; stores go to the file.
+    pub fn read_write(file: &File, len: usize) -> io::Result<Self> {
+        Self::map(file, len, libc::PROT_READ | libc::PROT_WRITE)
+    }
+
+    fn map(file: &File, len: usize, prot: i32) -> io::Result<Self> {
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot map zero bytes",
+            ));
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                prot,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: NonNull::new(ptr as *mut u8).expect("mmap returned NULL"),
+            len,
+        })
+    }
+
+    pub fn advise(&self, advice: Advice) -> io::Result<()> {
+        let rc = unsafe { libc::madvise(self.ptr.as_ptr() as *mut _, self.len, advice.raw()) };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self, mode: Msync) -> io::Result<()> {
+        let flags = match mode {
+            Msync::None => return Ok(()),
+            Msync::Async => libc::MS_ASYNC,
+            Msync::Sync => libc::MS_SYNC,
+        };
+        let rc = unsafe { libc::msync(self.ptr.as_ptr() as *mut _, self.len, flags) };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for Mapping {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr.as_ptr() as *mut _, self.len) };
+    }
+}
+
+/// Grow `file` to `len` bytes with real blocks behind them, so stores through
+/// a mapping don't have to allocate on the page-fault path. Falls back to
+/// ftruncate (a sparse file) where fallocate isn't supported.
+pub fn allocate(file: &File, len: u64) -> io::Result<()> {
+    let rc = unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len as i64) };
+    if rc == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EOPNOTSUPP) {
+        return file.set_len(len);
+    }
+    Err(err)
+}
+
+/// Minor and major page faults of this process so far (getrusage).
+pub fn page_faults() -> (u64, u64) {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) };
+    let usage = unsafe { usage.assume_init() };
+    (usage.ru_minflt as u64, usage.ru_majflt as u64)
+}
@@ -0,0 +1,233 @@
+// File names for the directory benchmarks. How names look changes how a
+// directory index treats them: ext4's htree and xfs hash them, btrfs keys by
+// hash too, tmpfs keeps a plain list. Every scheme is seeded, so the same
+// seed gives the same names in the same order on every run.
+//
+//   sequential  f000000, f000001, ...
+//   random      x + random digits, unique
+//   long        192-character random names
+//   prefix      random names behind a long shared prefix
+//   fanout=N    random names spread over N subdirectories picked by a hash
+//               of the name, like git's objects/ab/cdef... layout
+//
+// Order then decides which way round a set of names is walked, e.g. when
+// deleting them again.
+
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+use crate::bench::lookup;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    Sequential,
+    Random,
+    Long,
+    Prefix,
+    Fanout(u32),
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scheme::Sequential => f.write_str("sequential"),
+            Scheme::Random => f.write_str("random"),
+            Scheme::Long => f.write_str("long"),
+            Scheme::Prefix => f.write_str("prefix"),
+            Scheme::Fanout(dirs) => write!(f, "fanout={}", dirs),
+        }
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            None if s == "sequential" => Ok(Scheme::Sequential),
+            None if s == "random" => Ok(Scheme::Random),
+            None if s == "long" => Ok(Scheme::Long),
+            None if s == "prefix" => Ok(Scheme::Prefix),
+            Some(("fanout", n)) => match n.parse::<u32>().map_err(|e| e.to_string())? {
+                0 => Err("fanout needs at least one directory".into()),
+                n => Ok(Scheme::Fanout(n)),
+            },
+            _ => Err(format!(
+                "unknown naming scheme '{}' (expected sequential, random, long, prefix or fanout=N)",
+                s
+            )),
+        }
+    }
+}
+
+const LONG_LEN: usize = 192;
+const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+const PREFIX: &str = "shared_prefix_that_every_name_in_this_directory_starts_with_so_only_the_last_few_characters_tell_them_apart_";
+
+impl Scheme {
+    /// Subdirectories the names live in, relative to the target directory;
+    /// they have to exist before the files can be created.
+    pub fn dirs(self) -> Vec<String> {
+        match self {
+            Scheme::Fanout(n) => (0..n).map(|i| fanout_dir(i, n)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// `n` distinct names (relative paths, for fanout) in creation order.
+    pub fn generate(self, n: usize, seed: u64) -> Vec<String> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        match self {
+            Scheme::Sequential => {
+                let width = digits(n.saturating_sub(1) as u64).max(6);
+                (0..n).map(|i| format!("f{:0width$}", i)).collect()
+            }
+            Scheme::Random => {
+                let width = random_width(n);
+                unique(&mut rng, n, 10u64.pow(width as u32))
+                    .map(|v| format!("x{:0width$}", v))
+                    .collect()
+            }
+            Scheme::Long => {
+                let ids: Vec<u64> = unique(&mut rng, n, u64::MAX).collect();
+                ids.into_iter()
+                    .map(|id| {
+                        // random all the way through, unique thanks to the id at the end
+                        let mut bytes = [0u8; LONG_LEN - 13];
+                        rng.fill(&mut bytes[..]);
+                        let mut name: String = bytes
+                            .iter()
+                            .map(|&b| char::from(ALPHABET[b as usize % ALPHABET.len()]))
+                            .collect();
+                        name.push_str(&base36(id, 13));
+                        name
+                    })
+                    .collect()
+            }
+            Scheme::Prefix => {
+                let width = random_width(n);
+                unique(&mut rng, n, 10u64.pow(width as u32))
+                    .map(|v| format!("{}{:0width$}", PREFIX, v))
+                    .collect()
+            }
+            Scheme::Fanout(dirs) => {
+                let width = random_width(n);
+                unique(&mut rng, n, 10u64.pow(width as u32))
+                    .map(|v| {
+                        let name = format!("x{:0width$}", v);
+                        let dir = fanout_dir((fnv1a(name.as_bytes()) % dirs as u64) as u32, dirs);
+                        format!("{}/{}", dir, name)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The order to visit a set of names in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// As generated, i.e. the order they were created in.
+    Creation,
+    Sorted,
+    Reverse,
+    /// Seeded shuffle.
+    Shuffled,
+}
+
+impl Order {
+    pub const ALL: [Order; 4] = [
+        Order::Creation,
+        Order::Sorted,
+        Order::Reverse,
+        Order::Shuffled,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Order::Creation => "creation",
+            Order::Sorted => "sorted",
+            Order::Reverse => "reverse",
+            Order::Shuffled => "shuffled",
+        }
+    }
+
+    /// `names` (in creation order) rearranged.
+    pub fn arrange(self, names: &[String], seed: u64) -> Vec<&String> {
+        let mut out: Vec<&String> = names.iter().collect();
+        match self {
+            Order::Creation => {}
+            Order::Sorted => out.sort(),
+            Order::Reverse => out.sort_by(|a, b| b.cmp(a)),
+            Order::Shuffled => out.shuffle(&mut StdRng::seed_from_u64(seed)),
+        }
+        out
+    }
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lookup(&Order::ALL, s, |o| o.name(), "order")
+    }
+}
+
+fn digits(mut v: u64) -> usize {
+    let mut d = 1;
+    while v >= 10 {
+        v /= 10;
+        d += 1;
+    }
+    d
+}
+
+// Enough digits that n draws fill at most 1% of the space, so rejecting
+// repeats stays cheap; at least 8 so small runs look like big ones.
+fn random_width(n: usize) -> usize {
+    (digits(n as u64) + 2).clamp(8, 19)
+}
+
+// n distinct values below `limit`, in the order they were drawn. Repeats are
+// rejected through a hash set, which is O(n) expected as long as `limit` is
+// well above n (random_width keeps it 100 times larger).
+fn unique(rng: &mut StdRng, n: usize, limit: u64) -> impl Iterator<Item = u64> {
+    let mut seen = HashSet::with_capacity(n);
+    let mut values = Vec::with_capacity(n);
+    while values.len() < n {
+        let v = rng.gen_range(0..limit);
+        if seen.insert(v) {
+            values.push(v);
+        }
+    }
+    values.into_iter()
+}
+
+// `width` base-36 digits; 13 hold any u64.
+fn base36(mut v: u64, width: usize) -> String {
+    let mut out = vec![b'0'; width];
+    for c in out.iter_mut().rev() {
+        *c = ALPHABET[(v % 36) as usize];
+        v /= 36;
+    }
+    String::from_utf8(out).unwrap()
+}
+
+fn fanout_dir(i: u32, dirs: u32) -> String {
+    let width = format!("{:x}", dirs.saturating_sub(1)).len().max(2);
+    format!("{:0width$x}", i)
+}
+
+// FNV-1a, so the fanout doesn't change with the standard library's hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
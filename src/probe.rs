@@ -0,0 +1,261 @@
+// Find out, before opening anything, what O_DIRECT needs on a given path.
+//
+// Three sources, from most to least precise:
+//   * statx(STATX_DIOALIGN) (Linux 6.1+) reports the exact memory and offset
+//     alignment, or 0 when the file doesn't support direct I/O at all;
+//   * /sys/dev/block/MAJ:MIN/queue has the device's logical/physical block size;
+//   * fstatfs tells us the filesystem, which explains the failures.
+// A throwaway O_DIRECT open settles the cases none of those can answer.
+
+use std::{
+    ffi::CString,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io,
+    mem::MaybeUninit,
+    os::unix::{ffi::OsStrExt, fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+};
+
+use nix::sys::statfs::statfs;
+
+use crate::aligned_buf::DEFAULT_ALIGNMENT;
+
+#[derive(Clone, Debug)]
+pub struct DirectIoProbe {
+    /// Existing path the probe actually looked at (the parent, for new files).
+    pub probed: PathBuf,
+    pub fs_type: &'static str,
+    pub fs_magic: i64,
+    /// MAJ:MIN of the device the filesystem lives on.
+    pub device: (u32, u32),
+    pub logical_block_size: Option<usize>,
+    pub physical_block_size: Option<usize>,
+    /// From statx STATX_DIOALIGN, when the kernel knows it.
+    pub dio_mem_align: Option<usize>,
+    pub dio_offset_align: Option<usize>,
+    /// None if O_DIRECT should work, otherwise why it won't.
+    pub unsupported: Option<String>,
+}
+
+impl DirectIoProbe {
+    pub fn supported(&self) -> bool {
+        self.unsupported.is_none()
+    }
+
+    /// Alignment to use for buffers, lengths and offsets.
+    pub fn alignment(&self) -> usize {
+        [
+            self.dio_mem_align,
+            self.dio_offset_align,
+            self.logical_block_size,
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap_or(DEFAULT_ALIGNMENT)
+    }
+}
+
+impl fmt::Display for DirectIoProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: Option<usize>| v.map_or("unknown".to_string(), |n| n.to_string());
+        writeln!(f, "path:                {}", self.probed.display())?;
+        writeln!(
+            f,
+            "filesystem:          {} (0x{:x})",
+            self.fs_type, self.fs_magic
+        )?;
+        writeln!(
+            f,
+            "device:              {}:{}",
+            self.device.0, self.device.1
+        )?;
+        writeln!(f, "logical block size:  {}", show(self.logical_block_size))?;
+        writeln!(f, "physical block size: {}", show(self.physical_block_size))?;
+        writeln!(f, "dio memory align:    {}", show(self.dio_mem_align))?;
+        writeln!(f, "dio offset align:    {}", show(self.dio_offset_align))?;
+        writeln!(f, "alignment to use:    {}", self.alignment())?;
+        match &self.unsupported {
+            None => write!(f, "O_DIRECT:            supported"),
+            Some(why) => write!(f, "O_DIRECT:            not supported: {}", why),
+        }
+    }
+}
+
+/// Probe `path`, or its parent directory if the file doesn't exist yet.
+pub fn probe<P: AsRef<Path>>(path: P) -> io::Result<DirectIoProbe> {
+    let path = path.as_ref();
+    let exists = path.exists();
+    let probed = if exists {
+        path.to_path_buf()
+    } else {
+        match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    };
+
+    let fs = statfs(&probed).map_err(io::Error::from)?;
+    let fs_magic = fs.filesystem_type().0 as i64;
+    let fs_type = fs_name(fs_magic);
+
+    let stx = statx(&probed)?;
+    let device = (stx.stx_dev_major, stx.stx_dev_minor);
+    let (logical_block_size, physical_block_size) = block_sizes(device);
+
+    let (mut dio_mem_align, mut dio_offset_align) = (None, None);
+    let mut unsupported = None;
+    if stx.stx_mask & libc::STATX_DIOALIGN != 0 && exists && !probed.is_dir() {
+        if stx.stx_dio_mem_align == 0 {
+            unsupported = Some(format!(
+                "{} reports no direct I/O support for this file",
+                fs_type
+            ));
+        } else {
+            dio_mem_align = Some(stx.stx_dio_mem_align as usize);
+            dio_offset_align = Some(stx.stx_dio_offset_align as usize);
+        }
+    }
+
+    if unsupported.is_none() {
+        unsupported = try_open(path, fs_type);
+    }
+
+    Ok(DirectIoProbe {
+        probed,
+        fs_type,
+        fs_magic,
+        device,
+        logical_block_size,
+        physical_block_size,
+        dio_mem_align,
+        dio_offset_align,
+        unsupported,
+    })
+}
+
+/// Alignment for O_DIRECT on an already open file, falling back to
+/// [`DEFAULT_ALIGNMENT`] when nothing better is known.
+pub fn alignment(file: &File) -> usize {
+    let mut stx = MaybeUninit::<libc::statx>::zeroed();
+    let rc = unsafe {
+        libc::statx(
+            file.as_raw_fd(),
+            c"".as_ptr(),
+            libc::AT_EMPTY_PATH,
+            libc::STATX_DIOALIGN,
+            stx.as_mut_ptr(),
+        )
+    };
+    if rc != 0 {
+        return DEFAULT_ALIGNMENT;
+    }
+    let stx = unsafe { stx.assume_init() };
+
+    let logical = block_sizes((stx.stx_dev_major, stx.stx_dev_minor)).0;
+    let dio = if stx.stx_mask & libc::STATX_DIOALIGN != 0 {
+        Some(stx.stx_dio_mem_align.max(stx.stx_dio_offset_align) as usize)
+    } else {
+        None
+    };
+    [dio, logical]
+        .into_iter()
+        .flatten()
+        .filter(|&n| n.is_power_of_two())
+        .max()
+        .unwrap_or(DEFAULT_ALIGNMENT)
+}
+
+fn statx(path: &Path) -> io::Result<libc::statx> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stx = MaybeUninit::<libc::statx>::zeroed();
+    let rc = unsafe {
+        libc::statx(
+            libc::AT_FDCWD,
+            c_path.as_ptr(),
+            0,
+            libc::STATX_BASIC_STATS | libc::STATX_DIOALIGN,
+            stx.as_mut_ptr(),
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { stx.assume_init() })
+}
+
+/// Logical and physical block size from sysfs. Partitions don't have a queue
+/// directory of their own, so look at the parent disk too.
+fn block_sizes((major, minor): (u32, u32)) -> (Option<usize>, Option<usize>) {
+    let base = format!("/sys/dev/block/{}:{}", major, minor);
+    let read = |attr: &str| {
+        ["queue", "../queue"]
+            .iter()
+            .find_map(|dir| fs::read_to_string(format!("{}/{}/{}", base, dir, attr)).ok())
+            .and_then(|s| s.trim().parse::<usize>().ok())
+            .filter(|n| n.is_power_of_two())
+    };
+    (read("logical_block_size"), read("physical_block_size"))
+}
+
+// Open with O_DIRECT to see whether the filesystem accepts it. For anything
+// but an existing regular file, create a scratch file there and remove it again.
+fn try_open(path: &Path, fs_type: &str) -> Option<String> {
+    let scratch_name = format!(".odirect-probe.{}", std::process::id());
+    let scratch = if path.is_file() {
+        None
+    } else if path.is_dir() {
+        Some(path.join(scratch_name))
+    } else if !path.exists() {
+        let mut name = path.as_os_str().to_owned();
+        name.push(scratch_name);
+        Some(PathBuf::from(name))
+    } else {
+        return None;
+    };
+
+    let result = OpenOptions::new()
+        .read(true)
+        .write(scratch.is_some())
+        .create_new(scratch.is_some())
+        .custom_flags(libc::O_DIRECT)
+        .open(scratch.as_deref().unwrap_or(path));
+    if let Some(scratch) = &scratch {
+        let _ = fs::remove_file(scratch);
+    }
+
+    match result {
+        Ok(_) => None,
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => Some(format!(
+            "{} rejects O_DIRECT (open returned EINVAL); use buffered I/O plus fsync instead",
+            fs_type
+        )),
+        // Permission problems and the like aren't about O_DIRECT.
+        Err(_) => None,
+    }
+}
+
+/// Name for a statfs f_type magic number.
+pub fn fs_name(magic: i64) -> &'static str {
+    match magic {
+        0xEF53 => "ext2/3/4",
+        0x5846_5342 => "xfs",
+        0x9123_683E => "btrfs",
+        0x0102_1994 => "tmpfs",
+        0x8584_58f6 => "ramfs",
+        0x794c_7630 => "overlayfs",
+        0x6969 => "nfs",
+        0xFF53_4D42 => "cifs",
+        0x2fc1_2fc1 => "zfs",
+        0xF2F5_2010 => "f2fs",
+        0x6573_5546 => "fuse",
+        0x4d44 => "vfat",
+        0x2011_BAB0 => "exfat",
+        0x5346_544e => "ntfs",
+        0x0102_1997 => "9p",
+        0x9fa0 => "proc",
+        0x6265_6572 => "sysfs",
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,526 @@
+// Machine-readable results. Every benchmark binary takes
+//
+//   --format text|json|csv   (default text: the usual tables)
+//   --output FILE            (append records to FILE instead of stdout)
+//
+// and turns each row of its table into a Record. JSON is one object per line
+// so runs from many machines can simply be concatenated; CSV flattens the
+// same object into dotted column names. Every record carries the Metadata of
+// the machine and filesystem it was measured on.
+//
+// read_results loads either format back as Saved records for comparison.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{Map, Value, json};
+
+use crate::{cli::Args, histogram::Histogram, probe};
+
+/// Usage line for the options [`Reporter::from_args`] takes.
+pub const USAGE: &str = "[--format text|json|csv] [--output FILE]";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Text => "text",
+            Format::Json => "json",
+            Format::Csv => "csv",
+        })
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!(
+                "unknown format '{}' (expected text, json or csv)",
+                s
+            )),
+        }
+    }
+}
+
+/// Where and on what a benchmark ran. Everything is best effort: a field
+/// that can't be found is "unknown" rather than an error.
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    /// Seconds since the epoch when the record was made.
+    pub timestamp: u64,
+    pub hostname: String,
+    pub kernel: String,
+    pub cpu_model: String,
+    pub cpus: usize,
+    /// Filesystem and mount options of the benchmark's target path.
+    pub fs_type: String,
+    pub mount_options: String,
+}
+
+impl Metadata {
+    pub fn collect<P: AsRef<Path>>(target: P) -> Self {
+        let read = |path: &str| {
+            fs::read_to_string(path)
+                .map(|s| s.trim().to_string())
+                .unwrap_or_else(|_| "unknown".into())
+        };
+        let (fs_type, mount_options) =
+            mount_of(target.as_ref()).unwrap_or_else(|| ("unknown".into(), "unknown".into()));
+
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            hostname: read("/proc/sys/kernel/hostname"),
+            kernel: read("/proc/sys/kernel/osrelease"),
+            cpu_model: cpu_model().unwrap_or_else(|| "unknown".into()),
+            cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            fs_type,
+            mount_options,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "timestamp": self.timestamp,
+            "hostname": self.hostname,
+            "kernel": self.kernel,
+            "cpu_model": self.cpu_model,
+            "cpus": self.cpus,
+            "fs_type": self.fs_type,
+            "mount_options": self.mount_options,
+        })
+    }
+}
+
+fn cpu_model() -> Option<String> {
+    let info = fs::read_to_string("/proc/cpuinfo").ok()?;
+    // x86 has "model name"; arm64 only has the implementer/part numbers
+    ["model name", "Hardware", "CPU part"]
+        .iter()
+        .find_map(|key| {
+            info.lines().find_map(|line| {
+                let (k, v) = line.split_once(':')?;
+                (k.trim() == *key).then(|| v.trim().to_string())
+            })
+        })
+}
+
+// Filesystem type and options (per-mount and superblock) of the mount
+// holding `path`, from /proc/self/mountinfo. Falls back to the statfs magic
+// for the type if the path can't be resolved.
+fn mount_of(path: &Path) -> Option<(String, String)> {
+    let mut target = path.to_path_buf();
+    while !target.exists() {
+        target = match target.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+    }
+    let target = fs::canonicalize(&target).ok()?;
+
+    // 36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+    let info = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    let best = info
+        .lines()
+        .filter_map(|line| {
+            let (left, right) = line.split_once(" - ")?;
+            let mut left = left.split(' ');
+            let mount_point = unescape(left.nth(4)?);
+            let options = left.next()?;
+            let mut right = right.split(' ');
+            let fs_type = right.next()?;
+            let super_options = right.nth(1).unwrap_or("");
+            target.starts_with(&mount_point).then(|| {
+                (
+                    mount_point.as_os_str().len(),
+                    fs_type.to_string(),
+                    format!("{},{}", options, super_options),
+                )
+            })
+        })
+        // later mounts on the same point hide earlier ones
+        .max_by_key(|(len, _, _)| *len);
+
+    match best {
+        Some((_, fs_type, options)) => Some((fs_type, options)),
+        None => {
+            let magic = nix::sys::statfs::statfs(&target).ok()?.filesystem_type().0 as i64;
+            Some((probe::fs_name(magic).to_string(), "unknown".into()))
+        }
+    }
+}
+
+// mountinfo escapes spaces and friends as \040 etc.
+fn unescape(field: &str) -> PathBuf {
+    let mut out = Vec::with_capacity(field.len());
+    let bytes = field.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && i + 3 < bytes.len()
+            && let Ok(c) = u8::from_str_radix(&field[i + 1..i + 4], 8)
+        {
+            out.push(c);
+            i += 4;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    PathBuf::from(OsStr::from_bytes(&out))
+}
+
+/// One row of results: what was run with which parameters, and what came out.
+#[derive(Clone, Debug)]
+pub struct Record {
+    benchmark: String,
+    target: PathBuf,
+    run: Option<usize>,
+    params: Map<String, Value>,
+    results: Map<String, Value>,
+}
+
+impl Record {
+    /// `target` is the file or directory under test; its filesystem ends up
+    /// in the metadata.
+    pub fn new<P: AsRef<Path>>(benchmark: &str, target: P) -> Self {
+        Self {
+            benchmark: benchmark.to_string(),
+            target: target.as_ref().to_path_buf(),
+            run: None,
+            params: Map::new(),
+            results: Map::new(),
+        }
+    }
+
+    /// Which of several repetitions (counting from 0) this record is.
+    pub fn run(mut self, index: usize) -> Self {
+        self.run = Some(index);
+        self
+    }
+
+    pub fn param(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.params.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn result(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.results.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn elapsed(self, elapsed: Duration) -> Self {
+        self.result("elapsed_ns", elapsed.as_nanos() as u64)
+    }
+
+    /// count, mean, p50, p90, p99 and max of `h` as `<prefix>_<stat>_ns`.
+    pub fn latency(mut self, prefix: &str, h: &Histogram) -> Self {
+        let stats = [
+            ("mean", h.mean() as u64),
+            ("p50", h.percentile(50.0)),
+            ("p90", h.percentile(90.0)),
+            ("p99", h.percentile(99.0)),
+            ("max", h.max()),
+        ];
+        self = self.result(&format!("{}_count", prefix), h.count());
+        for (stat, value) in stats {
+            self = self.result(&format!("{}_{}_ns", prefix, stat), value);
+        }
+        self
+    }
+
+    fn to_json(&self, metadata: &Metadata) -> Value {
+        json!({
+            "benchmark": self.benchmark,
+            "run": self.run,
+            "metadata": metadata.to_json(),
+            "params": self.params,
+            "results": self.results,
+        })
+    }
+}
+
+/// Writes records in the format asked for on the command line.
+pub struct Reporter {
+    format: Format,
+    to_file: bool,
+    out: Box<dyn Write>,
+    // CSV columns: the header of the file appended to, or else fixed by the
+    // first record written
+    columns: Option<Vec<String>>,
+    // metadata is the same for every record on the same target
+    metadata: Option<(PathBuf, Metadata)>,
+}
+
+impl Reporter {
+    /// Take `--format` and `--output` from `args`.
+    pub fn from_args(args: &mut Args) -> Result<Self, String> {
+        let format = args.parse("--format")?.unwrap_or_default();
+        match args.value("--output")? {
+            Some(path) => Self::to_path(format, &path).map_err(|e| format!("{}: {}", path, e)),
+            None => Ok(Self::new(format, Box::new(io::stdout()))),
+        }
+    }
+
+    pub fn new(format: Format, out: Box<dyn Write>) -> Self {
+        Self {
+            format,
+            to_file: false,
+            out,
+            columns: None,
+            metadata: None,
+        }
+    }
+
+    /// Append to `path`. A CSV header is only written if the file is empty,
+    /// so repeated runs build up one table; otherwise rows are written under
+    /// the header already there.
+    pub fn to_path<P: AsRef<Path>>(format: Format, path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut reporter = Self::new(format, Box::new(io::BufWriter::new(file)));
+        reporter.to_file = true;
+        if format == Format::Csv && !empty {
+            let text = fs::read_to_string(path)?;
+            let header = text.lines().next().unwrap_or_default();
+            reporter.columns = Some(split_csv(header));
+        }
+        Ok(reporter)
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Whether the binary should print its usual human-readable output on
+    /// stdout: always, unless structured records are going there instead.
+    pub fn wants_text(&self) -> bool {
+        self.format == Format::Text || self.to_file
+    }
+
+    pub fn emit(&mut self, record: Record) -> io::Result<()> {
+        if self.format == Format::Text {
+            return Ok(());
+        }
+        let metadata = match &self.metadata {
+            Some((target, m)) if *target == record.target => m.clone(),
+            _ => {
+                let m = Metadata::collect(&record.target);
+                self.metadata = Some((record.target.clone(), m.clone()));
+                m
+            }
+        };
+        let value = record.to_json(&metadata);
+
+        match self.format {
+            Format::Text => {}
+            Format::Json => writeln!(self.out, "{}", value)?,
+            Format::Csv => {
+                let mut row = Vec::new();
+                flatten("", &value, &mut row);
+                let columns = match &self.columns {
+                    Some(columns) => columns,
+                    None => {
+                        let header: Vec<String> = row.iter().map(|(k, _)| k.clone()).collect();
+                        let line: Vec<String> = header.iter().map(|h| csv_field(h)).collect();
+                        writeln!(self.out, "{}", line.join(","))?;
+                        self.columns.insert(header)
+                    }
+                };
+                // a value with no column would be lost, or land under another
+                let extra: Vec<&str> = row
+                    .iter()
+                    .map(|(k, _)| k.as_str())
+                    .filter(|k| !columns.iter().any(|c| c == k))
+                    .collect();
+                if !extra.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "the CSV header has no column for {}; write to a new file",
+                            extra.join(", ")
+                        ),
+                    ));
+                }
+                let line: Vec<String> = columns
+                    .iter()
+                    .map(|c| {
+                        row.iter()
+                            .find(|(k, _)| k == c)
+                            .map_or(String::new(), |(_, v)| csv_field(v))
+                    })
+                    .collect();
+                writeln!(self.out, "{}", line.join(","))?;
+            }
+        }
+        self.out.flush()
+    }
+}
+
+// {"a": {"b": 1}} -> [("a.b", "1")]
+fn flatten(prefix: &str, value: &Value, out: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                let key = if prefix.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", prefix, k)
+                };
+                flatten(&key, v, out);
+            }
+        }
+        Value::String(s) => out.push((prefix.to_string(), s.clone())),
+        Value::Null => out.push((prefix.to_string(), String::new())),
+        other => out.push((prefix.to_string(), other.to_string())),
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// A record read back from a results file: what ran with which parameters,
+/// and its numeric results. Metadata is dropped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Saved {
+    pub benchmark: String,
+    pub run: Option<usize>,
+    /// Parameters as text, so JSON and CSV files compare equal.
+    pub params: BTreeMap<String, String>,
+    pub results: BTreeMap<String, f64>,
+}
+
+/// Load a file written with `--format json` or `--format csv`; which one is
+/// told apart by the first character.
+pub fn read_results<P: AsRef<Path>>(path: P) -> io::Result<Vec<Saved>> {
+    let text = fs::read_to_string(path)?;
+    let invalid = |line: usize, msg: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {}: {}", line, msg),
+        )
+    };
+
+    if text.trim_start().starts_with('{') {
+        let mut saved = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let value: Value =
+                serde_json::from_str(line).map_err(|e| invalid(i + 1, e.to_string()))?;
+            saved.push(
+                from_json(&value).ok_or_else(|| invalid(i + 1, "not a benchmark record".into()))?,
+            );
+        }
+        return Ok(saved);
+    }
+
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+    let Some((_, header)) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let header = split_csv(header);
+    let mut saved = Vec::new();
+    for (i, line) in lines {
+        let fields = split_csv(line);
+        if fields.len() != header.len() {
+            return Err(invalid(
+                i + 1,
+                format!("{} fields, header has {}", fields.len(), header.len()),
+            ));
+        }
+        let mut record = Saved::default();
+        for (column, field) in header.iter().zip(fields) {
+            if column == "benchmark" {
+                record.benchmark = field;
+            } else if column == "run" {
+                record.run = field.parse().ok();
+            } else if let Some(key) = column.strip_prefix("params.") {
+                record.params.insert(key.to_string(), field);
+            } else if let Some(key) = column.strip_prefix("results.")
+                && let Ok(v) = field.parse()
+            {
+                record.results.insert(key.to_string(), v);
+            }
+        }
+        saved.push(record);
+    }
+    Ok(saved)
+}
+
+fn from_json(value: &Value) -> Option<Saved> {
+    let text = |v: &Value| match v {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    };
+    Some(Saved {
+        benchmark: value.get("benchmark")?.as_str()?.to_string(),
+        run: value.get("run").and_then(Value::as_u64).map(|r| r as usize),
+        params: value
+            .get("params")?
+            .as_object()?
+            .iter()
+            .map(|(k, v)| (k.clone(), text(v)))
+            .collect(),
+        results: value
+            .get("results")?
+            .as_object()?
+            .iter()
+            .filter_map(|(k, v)| Some((k.clone(), v.as_f64()?)))
+            .collect(),
+    })
+}
+
+// The inverse of csv_field for one line: commas split fields except inside
+// quotes, and "" inside quotes is a literal quote.
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => field.push(c),
+        }
+    }
+    fields
+}
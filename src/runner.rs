@@ -0,0 +1,224 @@
+// Repeat a benchmark and summarise the spread, so one noisy run doesn't
+// decide anything. Binaries take
+//
+//   --warmup N   runs done first and thrown away (page cache, CPU frequency...)
+//   --repeat N   measured runs
+//
+// and print a Summary per configuration: mean, median, stddev, min/max, a 95%
+// confidence interval for the mean, and which runs look like outliers.
+
+use crate::cli::Args;
+
+/// Usage line for the options [`RunConfig::from_args`] takes.
+pub const USAGE: &str = "[--warmup N] [--repeat N]";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunConfig {
+    pub warmup: u32,
+    pub repeat: u32,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            warmup: 0,
+            repeat: 1,
+        }
+    }
+}
+
+impl RunConfig {
+    /// Take `--warmup` and `--repeat` from `args`.
+    pub fn from_args(args: &mut Args) -> Result<Self, String> {
+        let mut cfg = Self::default();
+        if let Some(warmup) = args.parse("--warmup")? {
+            cfg.warmup = warmup;
+        }
+        if let Some(repeat) = args.parse("--repeat")? {
+            cfg.repeat = repeat;
+        }
+        if cfg.repeat == 0 {
+            return Err("--repeat must be at least 1".into());
+        }
+        Ok(cfg)
+    }
+
+    /// Run `f` `warmup` times discarding the results, then `repeat` times
+    /// keeping them. Stops at the first error.
+    pub fn run<T, E>(&self, mut f: impl FnMut() -> Result<T, E>) -> Result<Vec<T>, E> {
+        for _ in 0..self.warmup {
+            f()?;
+        }
+        (0..self.repeat).map(|_| f()).collect()
+    }
+}
+
+/// Descriptive statistics of a set of samples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub n: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// Sample standard deviation (n - 1).
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Half width of the 95% confidence interval for the mean (Student's t).
+    pub ci95: f64,
+    /// Indices of samples outside Tukey's fences (1.5 IQR beyond the quartiles).
+    pub outliers: Vec<usize>,
+}
+
+impl Summary {
+    /// None for an empty slice.
+    pub fn of(samples: &[f64]) -> Option<Self> {
+        let n = samples.len();
+        if n == 0 {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let stddev = if n > 1 {
+            let var = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+            var.sqrt()
+        } else {
+            0.0
+        };
+        let ci95 = if n > 1 {
+            t_975(n - 1) * stddev / (n as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        let (q1, q3) = (quantile(&sorted, 0.25), quantile(&sorted, 0.75));
+        let iqr = q3 - q1;
+        let (low, high) = (q1 - 1.5 * iqr, q3 + 1.5 * iqr);
+        let outliers = samples
+            .iter()
+            .enumerate()
+            .filter(|&(_, &x)| x < low || x > high)
+            .map(|(i, _)| i)
+            .collect();
+
+        Some(Self {
+            n,
+            mean,
+            median: quantile(&sorted, 0.5),
+            stddev,
+            min: sorted[0],
+            max: sorted[n - 1],
+            ci95,
+            outliers,
+        })
+    }
+
+    /// stddev relative to the mean, in percent.
+    pub fn cv_percent(&self) -> f64 {
+        if self.mean == 0.0 {
+            0.0
+        } else {
+            self.stddev / self.mean * 100.0
+        }
+    }
+}
+
+// Linear interpolation between the closest ranks; `sorted` must be non-empty.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let pos = q * (sorted.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f64)
+}
+
+/// Two-sided 97.5% quantile of Student's t distribution with `df` degrees of
+/// freedom; tabulated up to 30, normal approximation beyond.
+pub fn t_975(df: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    match df {
+        0 => f64::INFINITY,
+        1..=30 => TABLE[df - 1],
+        _ => 1.960,
+    }
+}
+
+/// Welch's t-test for a difference between the means of two independent
+/// samples that needn't share a variance. Returns the t statistic of
+/// `b - a` and the Welch-Satterthwaite degrees of freedom, or None if either
+/// side has fewer than two samples.
+pub fn welch(a: &Summary, b: &Summary) -> Option<(f64, f64)> {
+    if a.n < 2 || b.n < 2 {
+        return None;
+    }
+    let (va, vb) = (a.stddev.powi(2) / a.n as f64, b.stddev.powi(2) / b.n as f64);
+    let diff = b.mean - a.mean;
+    if va + vb == 0.0 {
+        // no spread at all: any difference is as significant as it gets
+        let t = if diff == 0.0 {
+            0.0
+        } else {
+            diff.signum() * f64::INFINITY
+        };
+        return Some((t, (a.n + b.n - 2) as f64));
+    }
+    let t = diff / (va + vb).sqrt();
+    let df = (va + vb).powi(2) / (va.powi(2) / (a.n - 1) as f64 + vb.powi(2) / (b.n - 1) as f64);
+    Some((t, df))
+}
+
+/// Whether the means of `a` and `b` differ at the 95% level (two-sided
+/// Welch's t-test); None if there are too few samples to tell.
+pub fn significant(a: &Summary, b: &Summary) -> Option<bool> {
+    let (t, df) = welch(a, b)?;
+    // rounding df down keeps the test conservative
+    Some(t.abs() > t_975((df.floor() as usize).max(1)))
+}
+
+/// Print one summary row per configuration. `fmt` renders a value in the
+/// metric's unit (e.g. `fmt_nanos` for times).
+pub fn print_summary(metric: &str, rows: &[(String, Vec<f64>)], fmt: impl Fn(f64) -> String) {
+    println!(
+        "\n{:<32} {:>4} {:>10} {:>10} {:>10} {:>10} {:>10} {:>11} {:>6}  outliers",
+        metric, "n", "mean", "median", "stddev", "min", "max", "±95%", "cv%"
+    );
+    for (name, samples) in rows {
+        let Some(s) = Summary::of(samples) else {
+            continue;
+        };
+        let outliers: Vec<String> = s.outliers.iter().map(|i| format!("#{}", i + 1)).collect();
+        println!(
+            "{:<32} {:>4} {:>10} {:>10} {:>10} {:>10} {:>10} {:>11} {:>6.1}  {}",
+            name,
+            s.n,
+            fmt(s.mean),
+            fmt(s.median),
+            fmt(s.stddev),
+            fmt(s.min),
+            fmt(s.max),
+            format!("±{}", fmt(s.ci95)),
+            s.cv_percent(),
+            outliers.join(",")
+        );
+    }
+}
+
+/// Summary of `value` for results laid out as `repeat` consecutive runs per
+/// configuration, the way the benchmark binaries collect them.
+pub fn summarise<T>(
+    metric: &str,
+    results: &[T],
+    repeat: u32,
+    label: impl Fn(&T) -> String,
+    value: impl Fn(&T) -> f64,
+    fmt: impl Fn(f64) -> String,
+) {
+    let rows: Vec<(String, Vec<f64>)> = results
+        .chunks(repeat.max(1) as usize)
+        .map(|group| (label(&group[0]), group.iter().map(&value).collect()))
+        .collect();
+    print_summary(metric, &rows, fmt);
+}
@@ -0,0 +1,278 @@
+// fallocate(2) and a look at how a file is laid out: which ranges hold data
+// (SEEK_DATA/SEEK_HOLE), how many physical extents back it and whether any
+// of them are shared with a reflink copy (FIEMAP).
+
+use std::{
+    fmt,
+    fs::File,
+    io,
+    os::unix::{fs::MetadataExt, io::AsRawFd},
+    str::FromStr,
+};
+
+/// fallocate(2) operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Falloc {
+    /// Allocate blocks and grow the file to cover them.
+    Allocate,
+    /// FALLOC_FL_KEEP_SIZE: allocate blocks past EOF without changing the size.
+    KeepSize,
+    /// FALLOC_FL_PUNCH_HOLE: free the blocks, reads return zeroes.
+    PunchHole,
+    /// FALLOC_FL_ZERO_RANGE: zero the range, keeping (or allocating) blocks.
+    ZeroRange,
+}
+
+impl Falloc {
+    fn mode(self) -> i32 {
+        match self {
+            Falloc::Allocate => 0,
+            Falloc::KeepSize => libc::FALLOC_FL_KEEP_SIZE,
+            // punching a hole must not shrink the file, the kernel insists on KEEP_SIZE
+            Falloc::PunchHole => libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            Falloc::ZeroRange => libc::FALLOC_FL_ZERO_RANGE,
+        }
+    }
+}
+
+pub fn fallocate(file: &File, op: Falloc, offset: u64, len: u64) -> io::Result<()> {
+    let rc = unsafe { libc::fallocate(file.as_raw_fd(), op.mode(), offset as i64, len as i64) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Preallocation done by the write benchmarks before their first write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Prealloc {
+    /// Let the file grow write by write.
+    #[default]
+    None,
+    /// fallocate the whole file up front; it starts out full size.
+    Allocate,
+    /// fallocate with KEEP_SIZE: blocks reserved, size still grows with the writes.
+    KeepSize,
+}
+
+impl Prealloc {
+    /// Preallocate `len` bytes of `file`. Returns the number of syscalls made.
+    pub fn apply(self, file: &File, len: u64) -> io::Result<u64> {
+        let op = match self {
+            Prealloc::None => return Ok(0),
+            Prealloc::Allocate => Falloc::Allocate,
+            Prealloc::KeepSize => Falloc::KeepSize,
+        };
+        if len == 0 {
+            return Ok(0);
+        }
+        fallocate(file, op, 0, len)?;
+        Ok(1)
+    }
+}
+
+impl fmt::Display for Prealloc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Prealloc::None => "none",
+            Prealloc::Allocate => "allocate",
+            Prealloc::KeepSize => "keep-size",
+        })
+    }
+}
+
+impl FromStr for Prealloc {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Prealloc::None),
+            "allocate" => Ok(Prealloc::Allocate),
+            "keep-size" => Ok(Prealloc::KeepSize),
+            _ => Err(format!(
+                "unknown preallocation '{}' (expected none, allocate or keep-size)",
+                s
+            )),
+        }
+    }
+}
+
+/// A run of data or a hole, as seen by SEEK_DATA/SEEK_HOLE.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub offset: u64,
+    pub len: u64,
+    pub data: bool,
+}
+
+pub struct Layout {
+    pub size: u64,
+    pub segments: Vec<Segment>,
+    /// Bytes actually allocated on disk (st_blocks * 512); more than `size`
+    /// when blocks were reserved past EOF with KEEP_SIZE.
+    pub allocated: u64,
+    /// Physical extents reported by FIEMAP, if the filesystem supports it.
+    pub extents: Option<u32>,
+}
+
+impl Layout {
+    pub fn data_bytes(&self) -> u64 {
+        self.segments.iter().filter(|s| s.data).map(|s| s.len).sum()
+    }
+
+    pub fn hole_bytes(&self) -> u64 {
+        self.size - self.data_bytes()
+    }
+}
+
+pub fn layout(file: &File) -> io::Result<Layout> {
+    let meta = file.metadata()?;
+    Ok(Layout {
+        size: meta.len(),
+        segments: segments(file)?,
+        allocated: meta.blocks() * 512,
+        extents: physical_extents(file)?,
+    })
+}
+
+// -1/ENXIO from lseek means "no more data" (or "no more holes").
+fn seek(file: &File, offset: u64, whence: i32) -> io::Result<Option<u64>> {
+    let pos = unsafe { libc::lseek(file.as_raw_fd(), offset as i64, whence) };
+    if pos < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ENXIO) {
+            return Ok(None);
+        }
+        return Err(err);
+    }
+    Ok(Some(pos as u64))
+}
+
+/// Walk the file with SEEK_DATA/SEEK_HOLE. Filesystems without hole support
+/// report the whole file as one data segment.
+///
+/// This moves the file offset.
+pub fn segments(file: &File) -> io::Result<Vec<Segment>> {
+    let size = file.metadata()?.len();
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    while pos < size {
+        let data = seek(file, pos, libc::SEEK_DATA)?.unwrap_or(size).min(size);
+        if data > pos {
+            segments.push(Segment {
+                offset: pos,
+                len: data - pos,
+                data: false,
+            });
+        }
+        if data == size {
+            break;
+        }
+        // there's always a virtual hole at EOF
+        let hole = seek(file, data, libc::SEEK_HOLE)?.unwrap_or(size).min(size);
+        segments.push(Segment {
+            offset: data,
+            len: hole - data,
+            data: true,
+        });
+        pos = hole;
+    }
+    Ok(segments)
+}
+
+// struct fiemap from <linux/fiemap.h>, without the trailing extent array:
+// with fm_extent_count = 0 the kernel only counts the extents.
+#[repr(C)]
+#[derive(Default)]
+struct Fiemap {
+    fm_start: u64,
+    fm_length: u64,
+    fm_flags: u32,
+    fm_mapped_extents: u32,
+    fm_extent_count: u32,
+    fm_reserved: u32,
+}
+
+const FIEMAP_FLAG_SYNC: u32 = 0x1;
+// _IOWR('f', 11, struct fiemap)
+const FS_IOC_FIEMAP: libc::c_ulong = 0xC020_660B;
+
+/// Number of physical extents backing the file, flushing dirty data first so
+/// delayed allocation has happened. None where FIEMAP isn't supported (tmpfs).
+pub fn physical_extents(file: &File) -> io::Result<Option<u32>> {
+    let mut map = Fiemap {
+        fm_length: u64::MAX,
+        fm_flags: FIEMAP_FLAG_SYNC,
+        ..Default::default()
+    };
+    let rc = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP, &mut map) };
+    if rc != 0 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::EOPNOTSUPP | libc::ENOTTY) => Ok(None),
+            _ => Err(err),
+        };
+    }
+    Ok(Some(map.fm_mapped_extents))
+}
+
+// struct fiemap_extent from <linux/fiemap.h>.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FiemapExtent {
+    fe_logical: u64,
+    fe_physical: u64,
+    fe_length: u64,
+    fe_reserved64: [u64; 2],
+    fe_flags: u32,
+    fe_reserved: [u32; 3],
+}
+
+const FIEMAP_EXTENT_LAST: u32 = 0x1;
+const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+
+/// Whether any extent of the file shares its blocks with another file, i.e.
+/// the file is (partly) a reflink copy. None where FIEMAP isn't supported.
+pub fn is_shared(file: &File) -> io::Result<Option<bool>> {
+    const BATCH: usize = 64;
+
+    #[repr(C)]
+    struct Request {
+        map: Fiemap,
+        extents: [FiemapExtent; BATCH],
+    }
+
+    let mut start = 0;
+    loop {
+        let mut req = Request {
+            map: Fiemap {
+                fm_start: start,
+                fm_length: u64::MAX - start,
+                fm_flags: FIEMAP_FLAG_SYNC,
+                fm_extent_count: BATCH as u32,
+                ..Default::default()
+            },
+            extents: [FiemapExtent::default(); BATCH],
+        };
+        let rc = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP, &mut req) };
+        if rc != 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::EOPNOTSUPP | libc::ENOTTY) => Ok(None),
+                _ => Err(err),
+            };
+        }
+
+        let mapped = &req.extents[..req.map.fm_mapped_extents as usize];
+        if mapped
+            .iter()
+            .any(|e| e.fe_flags & FIEMAP_EXTENT_SHARED != 0)
+        {
+            return Ok(Some(true));
+        }
+        match mapped.last() {
+            Some(e) if e.fe_flags & FIEMAP_EXTENT_LAST == 0 => start = e.fe_logical + e.fe_length,
+            _ => return Ok(Some(false)),
+        }
+    }
+}